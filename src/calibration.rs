@@ -0,0 +1,260 @@
+//! Ranges and centers of input axes as measured by `calibrate`, which
+//! `run` uses instead of what the devices claim about themselves.
+
+use crate::configuration::JoystickAxes;
+use crate::event_codes::{self, EventCodeError};
+use evdev_rs::enums::{EventCode, EventType};
+use evdev_rs::DeviceWrapper;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CalibrationError {
+    #[error("Couldn't load config directory")]
+    NoConfigDir(#[from] xdg::BaseDirectoriesError),
+    #[error("Couldn't read or write calibration file")]
+    CalibrationFileIo(#[from] io::Error),
+    #[error("Couldn't parse calibration YAML")]
+    InvalidYaml(#[from] serde_yaml::Error),
+    #[error("Calibration for joystick {js:?} has a bad axis")]
+    BadAxis {
+        js: String,
+        #[source]
+        source: EventCodeError,
+    },
+    #[error(
+        "Calibration for joystick {js:?} axis {axis} needs min <= center <= max and min < max"
+    )]
+    BadRange { js: String, axis: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AxisCalibration {
+    pub min: i64,
+    pub center: i64,
+    pub max: i64,
+}
+
+impl AxisCalibration {
+    fn is_valid(&self) -> bool {
+        self.min <= self.center && self.center <= self.max && self.min < self.max
+    }
+}
+
+/// Keyed by the joystick's name in the config and then by axis name,
+/// e.g. `ABS_X`.
+pub type Calibration = BTreeMap<String, BTreeMap<String, AxisCalibration>>;
+
+pub fn calibration_file_path() -> Result<PathBuf, CalibrationError> {
+    let xdg_dirs = xdg::BaseDirectories::with_prefix("composite_joystick")?;
+    Ok(xdg_dirs.place_config_file("calibration.yaml")?)
+}
+
+/// Calibrating is optional, so a missing file is the same as an empty
+/// one.
+pub fn load_calibration_from<P: AsRef<Path>>(path: P) -> Result<Calibration, CalibrationError> {
+    match fs::read_to_string(path) {
+        Ok(calibration) => Ok(serde_yaml::from_str(&calibration)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Calibration::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn save_calibration_to<P: AsRef<Path>>(
+    path: P,
+    calibration: &Calibration,
+) -> Result<(), CalibrationError> {
+    fs::write(path, serde_yaml::to_string(calibration)?)?;
+    Ok(())
+}
+
+/// Replaces the bounds of every calibrated axis in `joysticks`.
+/// Calibrations for joysticks or axes that aren't there are ignored,
+/// since they're probably for an input that's commented out.
+pub fn apply(
+    calibration: &Calibration,
+    joysticks: &mut JoystickAxes,
+) -> Result<(), CalibrationError> {
+    for (js, axes) in calibration.iter() {
+        let joystick = match joysticks.get_mut(js) {
+            Some(joystick) => joystick,
+            None => continue,
+        };
+        for (name, axis_calibration) in axes.iter() {
+            let code = event_codes::parse(name).map_err(|source| CalibrationError::BadAxis {
+                js: js.clone(),
+                source,
+            })?;
+            if !axis_calibration.is_valid() {
+                return Err(CalibrationError::BadRange {
+                    js: js.clone(),
+                    axis: name.clone(),
+                });
+            }
+            if let Some(axis) = joystick.get_mut(&code) {
+                // Keep axes that a quirk inverted inverted.
+                if axis.lower_bound <= axis.upper_bound {
+                    axis.lower_bound = axis_calibration.min;
+                    axis.upper_bound = axis_calibration.max;
+                } else {
+                    axis.lower_bound = axis_calibration.max;
+                    axis.upper_bound = axis_calibration.min;
+                }
+                axis.center = Some(axis_calibration.center);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Keeps track of how far each axis of one joystick goes while the
+/// user sweeps it.
+#[derive(Debug)]
+pub struct Recorder {
+    axes: HashMap<EventCode, AxisCalibration>,
+}
+
+impl Recorder {
+    /// Starts from where each axis is resting.
+    pub fn new(centers: impl Iterator<Item = (EventCode, i64)>) -> Self {
+        Recorder {
+            axes: centers
+                .map(|(code, center)| {
+                    (
+                        code,
+                        AxisCalibration {
+                            min: center,
+                            center,
+                            max: center,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Takes the resting positions from `device`, which has to have
+    /// just been opened so that its axis values are current. Relative
+    /// axes rest at zero.
+    pub fn for_device(device: &evdev_rs::Device) -> Self {
+        let abs = evdev_rs::EventCodeIterator::new(&EventType::EV_ABS)
+            .filter_map(|code| Some((code, device.abs_info(&code)?.value.into())));
+        let rel = evdev_rs::EventCodeIterator::new(&EventType::EV_REL)
+            .filter(|code| device.has(*code))
+            .map(|code| (code, 0));
+        Self::new(abs.chain(rel))
+    }
+
+    pub fn observe(&mut self, code: EventCode, value: i64) {
+        if let Some(axis) = self.axes.get_mut(&code) {
+            axis.min = axis.min.min(value);
+            axis.max = axis.max.max(value);
+        }
+    }
+
+    /// Every axis that moved. Throttles and pedals rest at one end
+    /// rather than in the middle, so anything resting within a tenth
+    /// of an end is centered in the middle of its range instead.
+    pub fn finish(&self) -> BTreeMap<String, AxisCalibration> {
+        self.axes
+            .iter()
+            .filter(|(_, axis)| axis.min < axis.max)
+            .map(|(code, axis)| {
+                let margin = (axis.max - axis.min) / 10;
+                let center = if axis.center - axis.min <= margin || axis.max - axis.center <= margin
+                {
+                    axis.min + (axis.max - axis.min) / 2
+                } else {
+                    axis.center
+                };
+                (event_codes::name(code), AxisCalibration { center, ..*axis })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joystick_mux::{InputAxis, InputAxisId, JoystickId};
+    use evdev_rs::enums::{EV_ABS, EV_REL};
+
+    #[test]
+    fn test_recorder() {
+        let x = EventCode::EV_ABS(EV_ABS::ABS_X);
+        let throttle = EventCode::EV_ABS(EV_ABS::ABS_THROTTLE);
+        let wheel = EventCode::EV_REL(EV_REL::REL_WHEEL);
+        let mut recorder = Recorder::new([(x, 500), (throttle, 0), (wheel, 0)].into_iter());
+        for (code, value) in [(x, 20), (x, 1010), (throttle, 255), (x, 600)] {
+            recorder.observe(code, value);
+        }
+        assert_eq!(
+            recorder.finish(),
+            BTreeMap::from([
+                (
+                    "ABS_X".to_string(),
+                    AxisCalibration {
+                        min: 20,
+                        center: 500,
+                        max: 1010
+                    }
+                ),
+                (
+                    "ABS_THROTTLE".to_string(),
+                    AxisCalibration {
+                        min: 0,
+                        center: 127,
+                        max: 255
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let calibration: Calibration = serde_yaml::from_str(
+            "
+stick:
+  ABS_X: {min: 20, center: 500, max: 1010}
+  ABS_Y: {min: 0, center: 100, max: 200}
+gone:
+  ABS_X: {min: 0, center: 1, max: 2}
+",
+        )
+        .unwrap();
+        let x = EventCode::EV_ABS(EV_ABS::ABS_X);
+        let axis = InputAxis::new(
+            InputAxisId {
+                joystick: JoystickId(0),
+                axis: x,
+            },
+            0,
+            1023,
+        );
+        let mut joysticks =
+            JoystickAxes::from([("stick".to_string(), HashMap::from([(x, axis.clone())]))]);
+        apply(&calibration, &mut joysticks).unwrap();
+        assert_eq!(
+            joysticks["stick"][&x],
+            InputAxis {
+                lower_bound: 20,
+                upper_bound: 1010,
+                center: Some(500),
+                ..axis
+            }
+        );
+
+        let backwards: Calibration =
+            serde_yaml::from_str("stick: {ABS_X: {min: 10, center: 0, max: 5}}").unwrap();
+        assert!(matches!(
+            apply(&backwards, &mut joysticks),
+            Err(CalibrationError::BadRange { .. })
+        ));
+    }
+}
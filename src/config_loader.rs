@@ -1,6 +1,8 @@
+use evdev_rs::enums::EventCode;
 use serde::Deserialize;
 use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,46 +17,301 @@ pub enum ConfigLoaderError {
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
-    inputs: Vec<ConfigInput>,
-    outputs: Vec<ConfigOutput>,
+    pub inputs: Vec<ConfigInput>,
+    pub outputs: Vec<ConfigOutput>,
+    #[serde(default)]
+    pub report: ConfigReport,
+    /// Checked before the table that ships with Composite Joystick, so
+    /// these replace any entry there for the same device.
+    #[serde(default)]
+    pub quirks: Vec<ConfigQuirk>,
+    #[serde(default)]
+    pub layers: Vec<ConfigLayer>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ConfigInput {
-    device: String,
-    name: String,
+    pub device: Option<String>,
+    pub name: String,
+    #[serde(default, rename = "match")]
+    pub selector: ConfigDeviceSelector,
+    /// Keep the device's events from everything else on the machine.
+    #[serde(default)]
+    pub grab: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigDeviceSelector {
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub name: Option<String>,
+    #[serde(alias = "serial")]
+    pub uniq: Option<String>,
+    pub phys: Option<String>,
+}
+
+impl ConfigDeviceSelector {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Alternative bindings for inputs, switched to with the `axis` of
+/// joystick `js`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub name: String,
+    pub js: String,
+    #[serde(deserialize_with = "crate::event_codes::deserialize")]
+    pub axis: EventCode,
+    #[serde(default)]
+    pub mode: ConfigLayerMode,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigLayerMode {
+    #[default]
+    Momentary,
+    Latching,
+}
+
+/// Fixes for a device that describes itself wrong.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigQuirk {
+    /// Only for people reading the table.
+    pub name: Option<String>,
+    pub vendor: u16,
+    pub product: u16,
+    #[serde(default)]
+    pub axes: Vec<ConfigQuirkAxis>,
+    #[serde(default)]
+    pub buttons: Vec<ConfigQuirkButton>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigQuirkAxis {
+    #[serde(deserialize_with = "crate::event_codes::deserialize")]
+    pub axis: EventCode,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    #[serde(default)]
+    pub invert: bool,
+    /// How far from the middle of its range the axis rests.
+    pub center: Option<i64>,
+}
+
+/// Reports a button under a different event code, for buttons that the
+/// kernel maps somewhere odd.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigQuirkButton {
+    #[serde(deserialize_with = "crate::event_codes::deserialize")]
+    pub from: EventCode,
+    #[serde(deserialize_with = "crate::event_codes::deserialize")]
+    pub to: EventCode,
+}
+
+/// The shape of the emulated joysticks. Anything left out is the same
+/// as the default shape.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigReport {
+    pub axes: Option<Vec<ConfigAxisUsage>>,
+    pub hats: Option<usize>,
+    pub buttons: Option<usize>,
+    pub axis_bits: Option<usize>,
+    /// Reports per second to send at least, repeating the current state
+    /// if nothing's changed.
+    pub min_rate: Option<u32>,
+    /// Reports per second to send at most.
+    pub max_rate: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigAxisUsage {
+    X,
+    Y,
+    Z,
+    Rx,
+    Ry,
+    Rz,
+    Slider,
+    Dial,
+    Wheel,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigOutput {
-    axis_id: u16,
-    combine_fn: String,
-    inputs: Vec<ConfigInputAxis>,
+    #[serde(default)]
+    pub device_index: usize,
+    #[serde(deserialize_with = "crate::event_codes::deserialize")]
+    pub axis_id: EventCode,
+    pub combine_fn: ConfigCombineFn,
+    pub button_mode: Option<ConfigButtonMode>,
+    /// Written as e.g. `{Pulse: 100}` rather than with a YAML tag.
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub button_behavior: Option<ConfigButtonBehavior>,
+    pub priority_mode: Option<ConfigPriorityMode>,
+    /// Only used by the Chord combine_fn.
+    #[serde(default)]
+    pub suppress: bool,
+    pub inputs: Vec<ConfigInputAxis>,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigInputAxis {
-    js: String,
-    axis: u16,
+    pub js: String,
+    #[serde(deserialize_with = "crate::event_codes::deserialize")]
+    pub axis: EventCode,
+    #[serde(default)]
+    pub invert: bool,
+    /// Only used by the Sum combine_fn, defaults to 1.
+    pub weight: Option<f64>,
+    /// Only used by the Priority combine_fn, as a fraction of the
+    /// distance from center to either end. Defaults to 0.02.
+    pub threshold: Option<f64>,
+    /// Fractions of the distance from center to either end, default to
+    /// 0 and 1.
+    pub deadzone: Option<f64>,
+    pub saturation: Option<f64>,
+    /// Written as e.g. `{Exponential: 2.0}` rather than with a YAML tag.
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub curve: Option<ConfigCurve>,
+    /// Only for relative axes, defaults to Hold.
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub rel_mode: Option<ConfigRelMode>,
+    /// The name of the layer this input only counts in.
+    pub layer: Option<String>,
+    /// Only for buttons, written as e.g. `{Hold: 500}`.
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub press: Option<ConfigPress>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum ConfigPress {
+    Tap,
+    /// In milliseconds.
+    Hold(u64),
+    /// In milliseconds.
+    DoubleTap(u64),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum ConfigRelMode {
+    Hold,
+    /// In milliseconds.
+    Timeout(u64),
+    Integrate(f64),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum ConfigCurve {
+    Linear,
+    Exponential(f64),
+    SCurve(f64),
+    Table(Vec<(f64, f64)>),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
 pub enum ConfigCombineFn {
-    Max,
-    Hat {
-        x: ConfigInputAxis,
-        y: ConfigInputAxis,
+    #[serde(alias = "Max")]
+    LargestMagnitude,
+    Button,
+    Sum,
+    Priority,
+    Chord,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum ConfigPriorityMode {
+    Ranked,
+    LastMoved,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum ConfigButtonMode {
+    NonZero,
+    Positive,
+    Negative,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum ConfigButtonBehavior {
+    Follow,
+    Toggle,
+    /// In milliseconds.
+    Pulse(u64),
+    /// In milliseconds.
+    OnRelease(u64),
+    /// `rate` presses per second, pressed for `duty` of each, which
+    /// defaults to half.
+    Turbo {
+        rate: f64,
+        duty: Option<f64>,
     },
 }
 
-fn read_config_file() -> Result<String, ConfigLoaderError> {
+pub fn config_file_path() -> Result<PathBuf, ConfigLoaderError> {
     let xdg_dirs = xdg::BaseDirectories::with_prefix("composite_joystick")?;
-    let config = fs::read_to_string(xdg_dirs.place_config_file("config.yaml")?)?;
-    Ok(config)
+    Ok(xdg_dirs.place_config_file("config.yaml")?)
 }
 
-pub fn load_config_file() -> Result<Config, ConfigLoaderError> {
-    let config_string = read_config_file()?;
+pub fn load_config_from<P: AsRef<Path>>(path: P) -> Result<Config, ConfigLoaderError> {
+    let config_string = fs::read_to_string(path)?;
     let config = serde_yaml::from_str(&config_string)?;
     Ok(config)
 }
+
+pub fn load_config_file() -> Result<Config, ConfigLoaderError> {
+    load_config_from(config_file_path()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example_config_parses() {
+        let config: Config = serde_yaml::from_str(include_str!("../config.example.yaml"))
+            .expect("config.example.yaml should parse");
+        assert_eq!(config.inputs.len(), 3);
+        assert_eq!(config.outputs.len(), 53);
+    }
+
+    #[test]
+    fn test_grab() {
+        let inputs: Vec<ConfigInput> = serde_yaml::from_str(
+            "
+- {name: stick, device: /dev/null, grab: true}
+- {name: throttle, device: /dev/zero}
+",
+        )
+        .unwrap();
+        assert!(inputs[0].grab);
+        assert!(!inputs[1].grab);
+    }
+
+    #[test]
+    fn test_misspelled_keys() {
+        let output = "
+device_index: 0
+axis_id: ABS_X
+combine_fn: LargestMagnitude
+suppress: false
+inputs:
+  - {js: stick, axis: ABS_X, deadzone: 0.1}
+";
+        assert!(serde_yaml::from_str::<ConfigOutput>(output).is_ok());
+        for (key, typo) in [("suppress", "supress"), ("deadzone", "deadzon")] {
+            let err = serde_yaml::from_str::<ConfigOutput>(&output.replace(key, typo))
+                .expect_err("a misspelled key should be rejected");
+            assert!(err.to_string().contains(typo), "{err}");
+        }
+    }
+}
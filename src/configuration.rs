@@ -1,441 +1,1026 @@
-use crate::joystick_mux::{AxisCombineFn, ButtonMode, InputAxis, JoystickMux, OutputAxisId};
-use evdev_rs::enums::{EventCode, EV_ABS, EV_KEY, EV_REL};
-use std::collections::HashMap;
+use crate::config_loader::{
+    Config, ConfigAxisUsage, ConfigButtonBehavior, ConfigButtonMode, ConfigCombineFn, ConfigCurve,
+    ConfigInput, ConfigInputAxis, ConfigLayer, ConfigLayerMode, ConfigOutput, ConfigPress,
+    ConfigPriorityMode, ConfigRelMode, ConfigReport,
+};
+use crate::descriptor::{self, AxisUsage, ReportDescription};
+use crate::event_codes;
+use crate::gadget;
+use crate::joystick_mux::{
+    AxisCombineFn, ButtonBehavior, ButtonMode, Curve, InputAxis, JoystickMux, Layer, LayerId,
+    LayerMode, OutputAxisId, Press, PriorityMode, RelMode, ReportRate, Response,
+    OUTPUT_UPPER_BOUND,
+};
+use crate::report;
+use evdev_rs::enums::EventCode;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use thiserror::Error;
 
-pub fn configure_mux(
-    mux: &mut JoystickMux,
-    js_axes: &HashMap<EventCode, InputAxis>,
-    th_axes: &HashMap<EventCode, InputAxis>,
-    sp_axes: &HashMap<EventCode, InputAxis>,
-) {
-    mux.configure_axis(
-        // Yaw
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_RZ)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![
-                js_axes[&EventCode::EV_ABS(EV_ABS::ABS_X)],
-                sp_axes[&EventCode::EV_REL(EV_REL::REL_RZ)],
-            ],
-        },
-    );
-    mux.configure_axis(
-        // Pitch
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_RX)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![
-                js_axes[&EventCode::EV_ABS(EV_ABS::ABS_Y)],
-                sp_axes[&EventCode::EV_REL(EV_REL::REL_RX)],
-            ],
-        },
-    );
-    mux.configure_axis(
-        // Roll
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_RY)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![
-                js_axes[&EventCode::EV_ABS(EV_ABS::ABS_RZ)],
-                -sp_axes[&EventCode::EV_REL(EV_REL::REL_RY)],
-            ],
-        },
-    );
-    mux.configure_axis(
-        // Throttle/translate f/b
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_Y)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![
-                -sp_axes[&EventCode::EV_REL(EV_REL::REL_Y)],
-                -th_axes[&EventCode::EV_ABS(EV_ABS::ABS_Z)],
-            ],
-        },
-    );
-    mux.configure_axis(
-        // translate l/r
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![
-                sp_axes[&EventCode::EV_REL(EV_REL::REL_X)],
-                th_axes[&EventCode::EV_ABS(EV_ABS::ABS_X)],
-            ],
-        },
-    );
-    mux.configure_axis(
-        // translate u/d
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_Z)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![
-                sp_axes[&EventCode::EV_REL(EV_REL::REL_Z)],
-                th_axes[&EventCode::EV_ABS(EV_ABS::ABS_Y)],
-            ],
-        },
-    );
-    mux.configure_axis(
-        // dial
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_RUDDER)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![th_axes[&EventCode::EV_ABS(EV_ABS::ABS_RUDDER)]],
-        },
-    );
-    mux.configure_axis(
-        // slider
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_THROTTLE)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![th_axes[&EventCode::EV_ABS(EV_ABS::ABS_RZ)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_HAT0X)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![js_axes[&EventCode::EV_ABS(EV_ABS::ABS_HAT0X)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_HAT0Y)),
-        AxisCombineFn::LargestMagnitude {
-            inputs: vec![js_axes[&EventCode::EV_ABS(EV_ABS::ABS_HAT0Y)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // JS trigger
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_TRIGGER)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_THUMB)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // JS thumb
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_THUMB)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_THUMB2)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // JS thumb left
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_THUMB2)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TOP)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // JS thumb right
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_TOP)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TOP2)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle pinkie
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_THUMB)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_PINKIE)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle ring
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_THUMB2)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_BASE)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle switch up
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_TOP)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_BASE2)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle switch down
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_TOP2)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_BASE3)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle click stick
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_PINKIE)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_BASE4)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle thumb orange
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_TRIGGER)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_BASE5)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle middle hat up
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_BASE6)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle middle hat forward
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE2)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY1)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle middle hat down
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE3)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY2)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle middle hat back
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE4)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY3)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle bottom hat up
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE5)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY4)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle bottom hat forward
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE6)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY5)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle bottom hat down
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_300)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY6)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // throttle bottom hat back
-            inputs: vec![th_axes[&EventCode::EV_KEY(EV_KEY::BTN_301)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY7)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse macro 1
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_268)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY8)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse macro 2
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_269)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY9)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse macro 3
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_270)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY10)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse macro 4
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_271)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY11)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse esc
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_BACK)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY12)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse shift
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_280)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY13)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse ctrl
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_281)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY14)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse alt
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_TASK)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY15)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse rotate
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_8)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY16)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse T
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_2)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY17)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse middle
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_282)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY18)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse F
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_5)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY19)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse R
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_4)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY20)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse fit
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_1)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY21)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::Negative,
-            // throttle top hat up
-            inputs: vec![th_axes[&EventCode::EV_ABS(EV_ABS::ABS_HAT0Y)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY22)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::Positive,
-            // throttle top hat forward
-            inputs: vec![th_axes[&EventCode::EV_ABS(EV_ABS::ABS_HAT0X)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY23)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::Positive,
-            // throttle top hat down
-            inputs: vec![th_axes[&EventCode::EV_ABS(EV_ABS::ABS_HAT0Y)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY24)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::Negative,
-            // throttle top hat back
-            inputs: vec![th_axes[&EventCode::EV_ABS(EV_ABS::ABS_HAT0X)]],
-        },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY25)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // joystick base-left top-left
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_TOP2)]],
+#[derive(Error, Debug)]
+pub enum ConfigurationError {
+    #[error("Joystick {0:?} is not declared in inputs")]
+    UnknownJoystick(String),
+    #[error("Joystick {js:?} has no axis or button {}", event_codes::name(.axis))]
+    UnknownAxis { js: String, axis: EventCode },
+    #[error("Joystick {0:?} is declared more than once")]
+    DuplicateJoystick(String),
+    #[error("Joystick {0:?} needs either a device path or a match")]
+    NoDevice(String),
+    #[error("Joystick {0:?} has both a device path and a match")]
+    DeviceAndSelector(String),
+    #[error("Joysticks {0:?} and {1:?} are the same device")]
+    SameDevice(String, String),
+    #[error("Output {} has no slot in the emulated joystick", event_codes::name(.0))]
+    NoOutputSlot(EventCode),
+    #[error(
+        "Output {} is on device {0}, but there can be at most {} devices",
+        event_codes::name(.1),
+        gadget::MAX_DEVICES
+    )]
+    TooManyDevices(usize, EventCode),
+    #[error("Output {} on device {device} is bound more than once", event_codes::name(.axis))]
+    DuplicateOutput { device: usize, axis: EventCode },
+    #[error("Output {} is an axis but uses the Button combine_fn", event_codes::name(.0))]
+    ButtonOnAxis(EventCode),
+    #[error("Output {} is an axis but uses the Chord combine_fn", event_codes::name(.0))]
+    ChordOnAxis(EventCode),
+    #[error("Output {} sets suppress but doesn't use the Chord combine_fn", event_codes::name(.0))]
+    SuppressWithoutChord(EventCode),
+    #[error("Output {} sets button_mode but doesn't use the Button combine_fn", event_codes::name(.0))]
+    ButtonModeWithoutButton(EventCode),
+    #[error("Output {} sets button_behavior but doesn't use the Button combine_fn", event_codes::name(.0))]
+    ButtonBehaviorWithoutButton(EventCode),
+    #[error("Output {} has a Turbo rate that isn't above 0 and at most {}, or a duty that isn't strictly between 0 and 1", event_codes::name(.0), MAX_TURBO_RATE)]
+    BadTurbo(EventCode),
+    #[error("Output {} sets a weight on an input but doesn't use the Sum combine_fn", event_codes::name(.0))]
+    WeightWithoutSum(EventCode),
+    #[error("Output {} sets priority_mode but doesn't use the Priority combine_fn", event_codes::name(.0))]
+    PriorityModeWithoutPriority(EventCode),
+    #[error("Output {} sets a threshold on an input but doesn't use the Priority combine_fn", event_codes::name(.0))]
+    ThresholdWithoutPriority(EventCode),
+    #[error("Output {} shapes an input but uses the Button combine_fn", event_codes::name(.0))]
+    ResponseOnButton(EventCode),
+    #[error("Output {} has an input with {1}", event_codes::name(.0))]
+    BadResponse(EventCode, &'static str),
+    #[error("Quirks for device {vendor:04x}:{product:04x} are given more than once")]
+    DuplicateQuirk { vendor: u16, product: u16 },
+    #[error("Quirk for device {vendor:04x}:{product:04x} gives {} a min that isn't below its max", event_codes::name(.axis))]
+    QuirkRange {
+        vendor: u16,
+        product: u16,
+        axis: EventCode,
+    },
+    #[error("Output {} sets rel_mode on {}, which isn't a relative axis", event_codes::name(.0), event_codes::name(.1))]
+    RelModeOnAbsolute(EventCode, EventCode),
+    #[error("Output {} sets press on {}, which isn't a button", event_codes::name(.0), event_codes::name(.1))]
+    PressOnAxis(EventCode, EventCode),
+    #[error("The report can have at most {} axes, not {0}", descriptor::MAX_AXES)]
+    TooManyAxes(usize),
+    #[error("Axis {0:?} is in the report more than once")]
+    DuplicateReportAxis(AxisUsage),
+    #[error("The report can have at most {} hats, not {0}", descriptor::MAX_HATS)]
+    TooManyHats(usize),
+    #[error(
+        "The report can have at most {} buttons, not {0}",
+        descriptor::MAX_BUTTONS
+    )]
+    TooManyButtons(usize),
+    #[error("Axes can be 8 or 16 bits, not {0}")]
+    AxisBits(usize),
+    #[error("Report rates have to be above zero, and min_rate can't be above max_rate")]
+    ReportRate,
+    #[error("Layer {0:?} is not declared in layers")]
+    UnknownLayer(String),
+    #[error("Layer {0:?} is declared more than once")]
+    DuplicateLayer(String),
+}
+
+pub type JoystickAxes = HashMap<String, HashMap<EventCode, InputAxis>>;
+
+fn layer_id(layers: &[ConfigLayer], name: &str) -> Result<LayerId, ConfigurationError> {
+    layers
+        .iter()
+        .position(|layer| layer.name == name)
+        .map(LayerId)
+        .ok_or_else(|| ConfigurationError::UnknownLayer(name.to_string()))
+}
+
+fn input_axis(
+    joysticks: &JoystickAxes,
+    layers: &[ConfigLayer],
+    input: &ConfigInputAxis,
+) -> Result<InputAxis, ConfigurationError> {
+    let axes = joysticks
+        .get(&input.js)
+        .ok_or_else(|| ConfigurationError::UnknownJoystick(input.js.clone()))?;
+    let axis = InputAxis {
+        rel_mode: match input.rel_mode {
+            Some(ConfigRelMode::Hold) | None => RelMode::Hold,
+            Some(ConfigRelMode::Timeout(ms)) => RelMode::Timeout(Duration::from_millis(ms)),
+            Some(ConfigRelMode::Integrate(scale)) => RelMode::Integrate(scale),
         },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY26)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // joystick base-left top-mid
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_PINKIE)]],
+        response: response(input),
+        layer: input
+            .layer
+            .as_deref()
+            .map(|name| layer_id(layers, name))
+            .transpose()?,
+        press: input.press.map(|press| match press {
+            ConfigPress::Tap => Press::Tap,
+            ConfigPress::Hold(ms) => Press::Hold(Duration::from_millis(ms)),
+            ConfigPress::DoubleTap(ms) => Press::DoubleTap(Duration::from_millis(ms)),
+        }),
+        ..axes
+            .get(&input.axis)
+            .ok_or_else(|| ConfigurationError::UnknownAxis {
+                js: input.js.clone(),
+                axis: input.axis,
+            })?
+            .clone()
+    };
+    if input.invert {
+        Ok(-axis)
+    } else {
+        Ok(axis)
+    }
+}
+
+fn response(input: &ConfigInputAxis) -> Response {
+    let default = Response::default();
+    Response {
+        deadzone: input.deadzone.unwrap_or(default.deadzone),
+        saturation: input.saturation.unwrap_or(default.saturation),
+        curve: match &input.curve {
+            Some(ConfigCurve::Linear) => Curve::Linear,
+            Some(ConfigCurve::Exponential(k)) => Curve::Exponential(*k),
+            Some(ConfigCurve::SCurve(k)) => Curve::S(*k),
+            Some(ConfigCurve::Table(points)) => Curve::Table(points.clone()),
+            None => default.curve,
         },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY27)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // joystick base-left top-right
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE)]],
+    }
+}
+
+/// What's wrong with an input's response settings, if anything.
+/// The steepest an Exponential curve can be either way. Anything past
+/// this is all but a step anyway, and far enough past it the curve
+/// overflows.
+const MAX_EXPONENT: f64 = 20.0;
+
+fn check_response(response: &Response) -> Option<&'static str> {
+    if !(0.0..1.0).contains(&response.deadzone) {
+        return Some("a deadzone outside 0 to 1");
+    }
+    if !(response.saturation > response.deadzone && response.saturation <= 1.0) {
+        return Some("a saturation that isn't between its deadzone and 1");
+    }
+    match &response.curve {
+        Curve::Exponential(k) if !(-MAX_EXPONENT..=MAX_EXPONENT).contains(k) => {
+            Some("an Exponential curve outside -20 to 20")
+        }
+        Curve::S(k) if !(0.0..=1.0).contains(k) => Some("an SCurve outside 0 to 1"),
+        Curve::Table(points) => {
+            let in_range = points
+                .iter()
+                .all(|(x, y)| (0.0..=1.0).contains(x) && (0.0..=1.0).contains(y));
+            let sorted = points.windows(2).all(|pair| pair[0].0 <= pair[1].0);
+            if in_range && sorted {
+                None
+            } else {
+                Some("a curve Table that isn't sorted or goes outside 0 to 1")
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The most presses a second a Turbo button can be set to. It's only
+/// timed to within 10ms anyway.
+const MAX_TURBO_RATE: f64 = 1000.0;
+
+/// A Turbo button's behavior, or `None` if `rate` or `duty` is out of
+/// range.
+fn turbo(rate: f64, duty: Option<f64>) -> Option<ButtonBehavior> {
+    let duty = duty.unwrap_or(0.5);
+    if !(rate > 0.0 && rate <= MAX_TURBO_RATE && duty > 0.0 && duty < 1.0) {
+        return None;
+    }
+    let period = Duration::try_from_secs_f64(1.0 / rate).ok()?;
+    let on = period.mul_f64(duty);
+    Some(ButtonBehavior::Turbo {
+        on,
+        off: period - on,
+    })
+}
+
+/// How far from center a Priority input has to be to count as active,
+/// unless it sets its own threshold. Enough that a stick resting a
+/// little off center, or the odd unit of a range with no exact middle,
+/// isn't mistaken for someone using it.
+const DEFAULT_THRESHOLD: f64 = 0.02;
+
+fn combine_fn(
+    joysticks: &JoystickAxes,
+    layers: &[ConfigLayer],
+    output: &ConfigOutput,
+) -> Result<AxisCombineFn, ConfigurationError> {
+    let inputs = output
+        .inputs
+        .iter()
+        .map(|input| input_axis(joysticks, layers, input))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(match output.combine_fn {
+        ConfigCombineFn::LargestMagnitude => AxisCombineFn::LargestMagnitude { inputs },
+        ConfigCombineFn::Button => AxisCombineFn::Button {
+            mode: match output.button_mode.unwrap_or(ConfigButtonMode::NonZero) {
+                ConfigButtonMode::NonZero => ButtonMode::NonZero,
+                ConfigButtonMode::Positive => ButtonMode::Positive,
+                ConfigButtonMode::Negative => ButtonMode::Negative,
+            },
+            behavior: match output
+                .button_behavior
+                .unwrap_or(ConfigButtonBehavior::Follow)
+            {
+                ConfigButtonBehavior::Follow => ButtonBehavior::Follow,
+                ConfigButtonBehavior::Toggle => ButtonBehavior::Toggle,
+                ConfigButtonBehavior::Pulse(ms) => ButtonBehavior::Pulse(Duration::from_millis(ms)),
+                ConfigButtonBehavior::OnRelease(ms) => {
+                    ButtonBehavior::OnRelease(Duration::from_millis(ms))
+                }
+                ConfigButtonBehavior::Turbo { rate, duty } => {
+                    turbo(rate, duty).ok_or(ConfigurationError::BadTurbo(output.axis_id))?
+                }
+            },
+            inputs,
         },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY28)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // joystick base-left bottom-left
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE4)]],
+        ConfigCombineFn::Sum => AxisCombineFn::Sum {
+            inputs: inputs
+                .into_iter()
+                .zip(output.inputs.iter())
+                .map(|(axis, input)| (axis, input.weight.unwrap_or(1.0)))
+                .collect(),
         },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY29)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // joystick base-left bottom-middle
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE3)]],
+        ConfigCombineFn::Priority => AxisCombineFn::Priority {
+            mode: match output.priority_mode.unwrap_or(ConfigPriorityMode::Ranked) {
+                ConfigPriorityMode::Ranked => PriorityMode::Ranked,
+                ConfigPriorityMode::LastMoved => PriorityMode::LastMoved,
+            },
+            inputs: inputs
+                .into_iter()
+                .zip(output.inputs.iter())
+                .map(|(axis, input)| {
+                    let threshold = input.threshold.unwrap_or(DEFAULT_THRESHOLD);
+                    (axis, (threshold * OUTPUT_UPPER_BOUND as f64).round() as i64)
+                })
+                .collect(),
         },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY30)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // joystick base-left bottom-right
-            inputs: vec![js_axes[&EventCode::EV_KEY(EV_KEY::BTN_BASE2)]],
+        ConfigCombineFn::Chord => AxisCombineFn::Chord {
+            suppress: output.suppress,
+            inputs,
         },
-    );
-    mux.configure_axis(
-        OutputAxisId(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY31)),
-        AxisCombineFn::Button {
-            mode: ButtonMode::NonZero,
-            // spacemouse menu
-            inputs: vec![sp_axes[&EventCode::EV_KEY(EV_KEY::BTN_0)]],
+    })
+}
+
+pub fn report_description(report: &ConfigReport) -> ReportDescription {
+    let default = ReportDescription::default();
+    ReportDescription {
+        axes: match &report.axes {
+            Some(axes) => axes
+                .iter()
+                .map(|usage| match usage {
+                    ConfigAxisUsage::X => AxisUsage::X,
+                    ConfigAxisUsage::Y => AxisUsage::Y,
+                    ConfigAxisUsage::Z => AxisUsage::Z,
+                    ConfigAxisUsage::Rx => AxisUsage::Rx,
+                    ConfigAxisUsage::Ry => AxisUsage::Ry,
+                    ConfigAxisUsage::Rz => AxisUsage::Rz,
+                    ConfigAxisUsage::Slider => AxisUsage::Slider,
+                    ConfigAxisUsage::Dial => AxisUsage::Dial,
+                    ConfigAxisUsage::Wheel => AxisUsage::Wheel,
+                })
+                .collect(),
+            None => default.axes,
         },
-    );
+        hats: report.hats.unwrap_or(default.hats),
+        buttons: report.buttons.unwrap_or(default.buttons),
+        axis_bits: report.axis_bits.unwrap_or(default.axis_bits),
+    }
+}
+
+pub fn report_rate(report: &ConfigReport) -> ReportRate {
+    let interval = |rate: u32| Duration::from_secs(1) / rate.max(1);
+    ReportRate {
+        keepalive: report.min_rate.map(interval),
+        min_interval: report.max_rate.map(interval),
+    }
+}
+
+pub fn build_axes(
+    outputs: &[ConfigOutput],
+    layers: &[ConfigLayer],
+    joysticks: &JoystickAxes,
+) -> Result<HashMap<OutputAxisId, AxisCombineFn>, ConfigurationError> {
+    outputs
+        .iter()
+        .map(|output| {
+            Ok((
+                OutputAxisId {
+                    device: output.device_index,
+                    axis: output.axis_id,
+                },
+                combine_fn(joysticks, layers, output)?,
+            ))
+        })
+        .collect()
+}
+
+/// How many emulated joysticks `outputs` needs.
+pub fn output_devices(outputs: &[ConfigOutput]) -> usize {
+    outputs
+        .iter()
+        .map(|output| output.device_index + 1)
+        .max()
+        .unwrap_or(1)
+}
+
+pub fn layers(
+    layers: &[ConfigLayer],
+    joysticks: &JoystickAxes,
+) -> Result<Vec<Layer>, ConfigurationError> {
+    layers
+        .iter()
+        .map(|layer| {
+            let axes = joysticks
+                .get(&layer.js)
+                .ok_or_else(|| ConfigurationError::UnknownJoystick(layer.js.clone()))?;
+            let shift = axes
+                .get(&layer.axis)
+                .ok_or_else(|| ConfigurationError::UnknownAxis {
+                    js: layer.js.clone(),
+                    axis: layer.axis,
+                })?
+                .id;
+            Ok(Layer {
+                shift,
+                mode: match layer.mode {
+                    ConfigLayerMode::Momentary => LayerMode::Momentary,
+                    ConfigLayerMode::Latching => LayerMode::Latching,
+                },
+            })
+        })
+        .collect()
+}
+
+pub fn configure_mux(
+    mux: &mut JoystickMux,
+    outputs: &[ConfigOutput],
+    layers: &[ConfigLayer],
+    joysticks: &JoystickAxes,
+) -> Result<(), ConfigurationError> {
+    mux.set_layers(self::layers(layers, joysticks)?);
+    for (output_axis, combine_fn) in build_axes(outputs, layers, joysticks)? {
+        mux.configure_axis(output_axis, combine_fn);
+    }
+    Ok(())
+}
+
+/// Whether `a` and `b` name the same device in the same way. Different
+/// paths or selectors can still turn out to be one device once they're
+/// opened.
+fn same_device(a: &ConfigInput, b: &ConfigInput) -> bool {
+    match (&a.device, &b.device) {
+        (Some(a), Some(b)) => a == b,
+        (None, None) => a.selector == b.selector,
+        _ => false,
+    }
+}
+
+/// Finds everything wrong with `config` that can be found without
+/// opening any devices.
+pub fn check_config(config: &Config) -> Vec<ConfigurationError> {
+    let mut errors = Vec::new();
+
+    let mut joysticks = HashSet::new();
+    for (index, input) in config.inputs.iter().enumerate() {
+        if !joysticks.insert(&input.name) {
+            errors.push(ConfigurationError::DuplicateJoystick(input.name.clone()));
+        }
+        if let Some(other) = config.inputs[..index]
+            .iter()
+            .find(|other| same_device(other, input))
+        {
+            errors.push(ConfigurationError::SameDevice(
+                other.name.clone(),
+                input.name.clone(),
+            ));
+        }
+        match (&input.device, input.selector.is_empty()) {
+            (None, true) => errors.push(ConfigurationError::NoDevice(input.name.clone())),
+            (Some(_), false) => {
+                errors.push(ConfigurationError::DeviceAndSelector(input.name.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    let mut quirks = HashSet::new();
+    for quirk in config.quirks.iter() {
+        let (vendor, product) = (quirk.vendor, quirk.product);
+        if !quirks.insert((vendor, product)) {
+            errors.push(ConfigurationError::DuplicateQuirk { vendor, product });
+        }
+        for fix in quirk.axes.iter() {
+            if let (Some(min), Some(max)) = (fix.min, fix.max) {
+                if min >= max {
+                    errors.push(ConfigurationError::QuirkRange {
+                        vendor,
+                        product,
+                        axis: fix.axis,
+                    });
+                }
+            }
+        }
+    }
+
+    let description = report_description(&config.report);
+    if description.axes.len() > descriptor::MAX_AXES {
+        errors.push(ConfigurationError::TooManyAxes(description.axes.len()));
+    }
+    let mut usages = HashSet::new();
+    for usage in description.axes.iter() {
+        if !usages.insert(usage) {
+            errors.push(ConfigurationError::DuplicateReportAxis(*usage));
+        }
+    }
+    if description.hats > descriptor::MAX_HATS {
+        errors.push(ConfigurationError::TooManyHats(description.hats));
+    }
+    if description.buttons > descriptor::MAX_BUTTONS {
+        errors.push(ConfigurationError::TooManyButtons(description.buttons));
+    }
+    if !descriptor::AXIS_BITS.contains(&description.axis_bits) {
+        errors.push(ConfigurationError::AxisBits(description.axis_bits));
+    }
+    match (config.report.min_rate, config.report.max_rate) {
+        (Some(0), _) | (_, Some(0)) => errors.push(ConfigurationError::ReportRate),
+        (Some(min), Some(max)) if min > max => errors.push(ConfigurationError::ReportRate),
+        _ => {}
+    }
+
+    let mut layers = HashSet::new();
+    for layer in config.layers.iter() {
+        if !layers.insert(&layer.name) {
+            errors.push(ConfigurationError::DuplicateLayer(layer.name.clone()));
+        }
+        if !joysticks.contains(&layer.js) {
+            errors.push(ConfigurationError::UnknownJoystick(layer.js.clone()));
+        }
+    }
+
+    let mut outputs = HashSet::new();
+    for output in config.outputs.iter() {
+        let code = output.axis_id;
+        if !report::has_slot(&description, code) {
+            errors.push(ConfigurationError::NoOutputSlot(code));
+        }
+        if output.device_index >= gadget::MAX_DEVICES {
+            errors.push(ConfigurationError::TooManyDevices(
+                output.device_index,
+                code,
+            ));
+        }
+        if !outputs.insert((output.device_index, code)) {
+            errors.push(ConfigurationError::DuplicateOutput {
+                device: output.device_index,
+                axis: code,
+            });
+        }
+        match output.combine_fn {
+            ConfigCombineFn::Button => {
+                if !matches!(code, EventCode::EV_KEY(_)) {
+                    errors.push(ConfigurationError::ButtonOnAxis(code));
+                }
+                if let Some(ConfigButtonBehavior::Turbo { rate, duty }) = output.button_behavior {
+                    if turbo(rate, duty).is_none() {
+                        errors.push(ConfigurationError::BadTurbo(code));
+                    }
+                }
+            }
+            ConfigCombineFn::Chord => {
+                if !matches!(code, EventCode::EV_KEY(_)) {
+                    errors.push(ConfigurationError::ChordOnAxis(code));
+                }
+                if output.button_mode.is_some() {
+                    errors.push(ConfigurationError::ButtonModeWithoutButton(code));
+                }
+                if output.button_behavior.is_some() {
+                    errors.push(ConfigurationError::ButtonBehaviorWithoutButton(code));
+                }
+            }
+            ConfigCombineFn::LargestMagnitude
+            | ConfigCombineFn::Sum
+            | ConfigCombineFn::Priority => {
+                if output.button_mode.is_some() {
+                    errors.push(ConfigurationError::ButtonModeWithoutButton(code));
+                }
+                if output.button_behavior.is_some() {
+                    errors.push(ConfigurationError::ButtonBehaviorWithoutButton(code));
+                }
+            }
+        }
+        let is_sum = matches!(output.combine_fn, ConfigCombineFn::Sum);
+        if !is_sum && output.inputs.iter().any(|input| input.weight.is_some()) {
+            errors.push(ConfigurationError::WeightWithoutSum(code));
+        }
+        let is_priority = matches!(output.combine_fn, ConfigCombineFn::Priority);
+        if !is_priority && output.priority_mode.is_some() {
+            errors.push(ConfigurationError::PriorityModeWithoutPriority(code));
+        }
+        if !is_priority && output.inputs.iter().any(|input| input.threshold.is_some()) {
+            errors.push(ConfigurationError::ThresholdWithoutPriority(code));
+        }
+        let is_chord = matches!(output.combine_fn, ConfigCombineFn::Chord);
+        if !is_chord && output.suppress {
+            errors.push(ConfigurationError::SuppressWithoutChord(code));
+        }
+        let shaped = output.inputs.iter().any(|input| {
+            input.deadzone.is_some() || input.saturation.is_some() || input.curve.is_some()
+        });
+        if shaped && matches!(output.combine_fn, ConfigCombineFn::Button) {
+            errors.push(ConfigurationError::ResponseOnButton(code));
+        }
+        for input in output.inputs.iter() {
+            if let Some(problem) = check_response(&response(input)) {
+                errors.push(ConfigurationError::BadResponse(code, problem));
+            }
+            if matches!(input.threshold, Some(threshold) if !(0.0..1.0).contains(&threshold)) {
+                errors.push(ConfigurationError::BadResponse(
+                    code,
+                    "a threshold outside 0 to 1",
+                ));
+            }
+            if matches!(input.weight, Some(weight) if !weight.is_finite()) {
+                errors.push(ConfigurationError::BadResponse(
+                    code,
+                    "a weight that isn't a number",
+                ));
+            }
+            if input.rel_mode.is_some() && !matches!(input.axis, EventCode::EV_REL(_)) {
+                errors.push(ConfigurationError::RelModeOnAbsolute(code, input.axis));
+            }
+            if input.press.is_some() && !matches!(input.axis, EventCode::EV_KEY(_)) {
+                errors.push(ConfigurationError::PressOnAxis(code, input.axis));
+            }
+        }
+        for input in output.inputs.iter() {
+            if !joysticks.contains(&input.js) {
+                errors.push(ConfigurationError::UnknownJoystick(input.js.clone()));
+            }
+            match &input.layer {
+                Some(layer) if !layers.contains(layer) => {
+                    errors.push(ConfigurationError::UnknownLayer(layer.clone()))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joystick_mux::{InputAxisId, JoystickId, OutputState};
+    use evdev_rs::enums::{EV_ABS, EV_KEY};
+
+    fn joysticks() -> JoystickAxes {
+        let stick = [
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        ]
+        .into_iter()
+        .map(|code| {
+            (
+                code,
+                InputAxis::new(
+                    InputAxisId {
+                        joystick: JoystickId(0),
+                        axis: code,
+                    },
+                    -5,
+                    5,
+                ),
+            )
+        })
+        .collect();
+        [("stick".to_string(), stick)].into_iter().collect()
+    }
+
+    fn outputs(yaml: &str) -> Vec<ConfigOutput> {
+        serde_yaml::from_str(yaml).expect("test config should parse")
+    }
+
+    #[test]
+    fn test_configure_axes_and_buttons() {
+        let mut m = JoystickMux::new(None);
+        configure_mux(
+            &mut m,
+            &outputs(
+                "
+- axis_id: ABS_RZ
+  combine_fn: LargestMagnitude
+  inputs: [{js: stick, axis: ABS_X, invert: true}]
+- axis_id: BTN_TRIGGER
+  combine_fn: Button
+  inputs: [{js: stick, axis: BTN_TRIGGER}]
+",
+            ),
+            &[],
+            &joysticks(),
+        )
+        .unwrap();
+        assert_eq!(
+            m.output(),
+            OutputState {
+                time: None,
+                axes: vec![
+                    (
+                        OutputAxisId {
+                            device: 0,
+                            axis: EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+                        },
+                        0
+                    ),
+                    (
+                        OutputAxisId {
+                            device: 0,
+                            axis: EventCode::EV_ABS(EV_ABS::ABS_RZ),
+                        },
+                        0
+                    ),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_layers() {
+        let layers: Vec<ConfigLayer> = serde_yaml::from_str(
+            "
+- {name: base, js: stick, axis: BTN_TRIGGER}
+- {name: shift, js: stick, axis: BTN_TRIGGER, mode: Latching}
+",
+        )
+        .unwrap();
+        assert_eq!(
+            self::layers(&layers, &joysticks()).unwrap()[1],
+            Layer {
+                shift: InputAxisId {
+                    joystick: JoystickId(0),
+                    axis: EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+                },
+                mode: LayerMode::Latching,
+            }
+        );
+        let axes = build_axes(
+            &outputs(
+                "
+- axis_id: ABS_X
+  combine_fn: LargestMagnitude
+  inputs: [{js: stick, axis: ABS_X, layer: shift}]
+",
+            ),
+            &layers,
+            &joysticks(),
+        )
+        .unwrap();
+        let output = OutputAxisId {
+            device: 0,
+            axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+        };
+        assert_eq!(axes[&output].inputs()[0].layer, Some(LayerId(1)));
+    }
+
+    #[test]
+    fn test_sum_weights() {
+        let axes = build_axes(
+            &outputs(
+                "
+- axis_id: ABS_X
+  combine_fn: Sum
+  inputs:
+    - {js: stick, axis: ABS_X}
+    - {js: stick, axis: ABS_X, invert: true, weight: 0.1}
+",
+            ),
+            &[],
+            &joysticks(),
+        )
+        .unwrap();
+        let output = OutputAxisId {
+            device: 0,
+            axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+        };
+        match &axes[&output] {
+            AxisCombineFn::Sum { inputs } => {
+                assert_eq!(inputs[0].1, 1.0);
+                assert_eq!(inputs[1].1, 0.1);
+                assert_eq!(inputs[1].0.lower_bound, 5);
+            }
+            other => panic!("Expected Sum, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_turbo_bounds() {
+        let turbo_output = |behavior: &str| {
+            outputs(&format!(
+                "
+- axis_id: BTN_TRIGGER
+  combine_fn: Button
+  button_behavior: {{Turbo: {behavior}}}
+  inputs: [{{js: stick, axis: BTN_TRIGGER}}]
+"
+            ))
+        };
+        for behavior in [
+            "{rate: 1.0e-320}",
+            "{rate: 0}",
+            "{rate: 1001}",
+            "{rate: .nan}",
+            "{rate: 10, duty: 0.0}",
+            "{rate: 10, duty: 1.0}",
+        ] {
+            let result = build_axes(&turbo_output(behavior), &[], &joysticks());
+            assert!(
+                matches!(result, Err(ConfigurationError::BadTurbo(_))),
+                "{behavior} should be rejected"
+            );
+        }
+        let axes =
+            build_axes(&turbo_output("{rate: 1000, duty: 0.25}"), &[], &joysticks()).unwrap();
+        let output = OutputAxisId {
+            device: 0,
+            axis: EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        };
+        match &axes[&output] {
+            AxisCombineFn::Button {
+                behavior: ButtonBehavior::Turbo { on, off },
+                ..
+            } => {
+                assert_eq!(*on, Duration::from_micros(250));
+                assert_eq!(*off, Duration::from_micros(750));
+            }
+            other => panic!("Expected Turbo, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_joystick() {
+        let mut m = JoystickMux::new(None);
+        let result = configure_mux(
+            &mut m,
+            &outputs(
+                "
+- axis_id: ABS_X
+  combine_fn: Max
+  inputs: [{js: throttle, axis: ABS_X}]
+",
+            ),
+            &[],
+            &joysticks(),
+        );
+        assert!(matches!(
+            result,
+            Err(ConfigurationError::UnknownJoystick(name)) if name == "throttle"
+        ));
+    }
+
+    #[test]
+    fn test_unknown_axis() {
+        let mut m = JoystickMux::new(None);
+        let result = configure_mux(
+            &mut m,
+            &outputs(
+                "
+- axis_id: ABS_X
+  combine_fn: LargestMagnitude
+  inputs: [{js: stick, axis: ABS_Y}]
+",
+            ),
+            &[],
+            &joysticks(),
+        );
+        assert!(matches!(
+            result,
+            Err(ConfigurationError::UnknownAxis {
+                axis: EventCode::EV_ABS(EV_ABS::ABS_Y),
+                ..
+            })
+        ));
+    }
+
+    fn check(yaml: &str) -> Vec<String> {
+        let config: Config = serde_yaml::from_str(yaml).expect("test config should parse");
+        check_config(&config)
+            .into_iter()
+            .map(|err| err.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_check_example_config() {
+        assert_eq!(
+            check(include_str!("../config.example.yaml")),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_check_finds_problems() {
+        assert_eq!(
+            check(
+                "
+inputs:
+  - {name: stick, device: /dev/null}
+  - {name: stick, device: /dev/zero}
+  - {name: pedals}
+  - {name: throttle2, device: /dev/null, match: {vendor: 0x044f}}
+outputs:
+  - axis_id: ABS_X
+    combine_fn: Button
+    inputs: [{js: stick, axis: BTN_TRIGGER}]
+  - axis_id: ABS_X
+    combine_fn: LargestMagnitude
+    button_mode: Positive
+    button_behavior: Toggle
+    inputs: [{js: throttle, axis: ABS_X}]
+  - axis_id: ABS_MISC
+    combine_fn: LargestMagnitude
+    inputs: []
+  - axis_id: BTN_BASE
+    device_index: 100
+    combine_fn: Button
+    inputs: []
+  - axis_id: ABS_Y
+    combine_fn: LargestMagnitude
+    inputs: [{js: stick, axis: ABS_Y, weight: 0.5, rel_mode: {Timeout: 100}, press: Tap}]
+  - axis_id: ABS_Z
+    combine_fn: Sum
+    priority_mode: LastMoved
+    suppress: true
+    inputs: [{js: stick, axis: ABS_Z, deadzone: 0.5, saturation: 0.4, threshold: 1.5}]
+  - axis_id: ABS_RY
+    combine_fn: Sum
+    inputs:
+      - {js: stick, axis: ABS_RY, weight: .nan, curve: {Exponential: .inf}}
+      - {js: stick, axis: ABS_RZ, saturation: .nan, weight: -.inf}
+      - {js: stick, axis: ABS_THROTTLE, curve: {Exponential: 1000}}
+  - axis_id: ABS_RX
+    combine_fn: Chord
+    inputs: [{js: stick, axis: BTN_TRIGGER}, {js: stick, axis: BTN_THUMB}]
+  - axis_id: BTN_TRIGGER
+    combine_fn: Button
+    button_behavior: {Turbo: {rate: 10, duty: 1.5}}
+    inputs: [{js: stick, axis: BTN_TRIGGER}]
+  - axis_id: BTN_THUMB
+    combine_fn: Button
+    inputs: [{js: stick, axis: BTN_THUMB, curve: {Table: [[0.5, 0.5], [0.2, 1]]}, layer: alt}]
+quirks:
+  - {vendor: 0x046d, product: 0xc62b, axes: [{axis: REL_X, min: 350, max: -350}]}
+  - {vendor: 0x046d, product: 0xc62b}
+layers:
+  - {name: shift, js: stick, axis: BTN_BASE}
+  - {name: shift, js: spacemouse, axis: BTN_0}
+"
+            ),
+            vec![
+                "Joystick \"stick\" is declared more than once",
+                "Joystick \"pedals\" needs either a device path or a match",
+                "Joysticks \"stick\" and \"throttle2\" are the same device",
+                "Joystick \"throttle2\" has both a device path and a match",
+                "Quirk for device 046d:c62b gives REL_X a min that isn't below its max",
+                "Quirks for device 046d:c62b are given more than once",
+                "Layer \"shift\" is declared more than once",
+                "Joystick \"spacemouse\" is not declared in inputs",
+                "Output ABS_X is an axis but uses the Button combine_fn",
+                "Output ABS_X on device 0 is bound more than once",
+                "Output ABS_X sets button_mode but doesn't use the Button combine_fn",
+                "Output ABS_X sets button_behavior but doesn't use the Button combine_fn",
+                "Joystick \"throttle\" is not declared in inputs",
+                "Output ABS_MISC has no slot in the emulated joystick",
+                "Output BTN_BASE is on device 100, but there can be at most 4 devices",
+                "Output ABS_Y sets a weight on an input but doesn't use the Sum combine_fn",
+                "Output ABS_Y sets rel_mode on ABS_Y, which isn't a relative axis",
+                "Output ABS_Y sets press on ABS_Y, which isn't a button",
+                "Output ABS_Z sets priority_mode but doesn't use the Priority combine_fn",
+                "Output ABS_Z sets a threshold on an input but doesn't use the Priority combine_fn",
+                "Output ABS_Z sets suppress but doesn't use the Chord combine_fn",
+                "Output ABS_Z has an input with a saturation that isn't between its deadzone and 1",
+                "Output ABS_Z has an input with a threshold outside 0 to 1",
+                "Output ABS_RY has an input with an Exponential curve outside -20 to 20",
+                "Output ABS_RY has an input with a weight that isn't a number",
+                "Output ABS_RY has an input with a saturation that isn't between its deadzone and 1",
+                "Output ABS_RY has an input with a weight that isn't a number",
+                "Output ABS_RY has an input with an Exponential curve outside -20 to 20",
+                "Output ABS_RX is an axis but uses the Chord combine_fn",
+                "Output BTN_TRIGGER has a Turbo rate that isn't above 0 and at most 1000, or a duty that isn't strictly between 0 and 1",
+                "Output BTN_THUMB shapes an input but uses the Button combine_fn",
+                "Output BTN_THUMB has an input with a curve Table that isn't sorted or goes outside 0 to 1",
+                "Layer \"alt\" is not declared in layers",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_device_twice() {
+        assert_eq!(
+            check(
+                "
+inputs:
+  - {name: left, match: {vendor: 0x044f, phys: usb-1/input0}}
+  - {name: right, match: {vendor: 0x044f, phys: usb-1/input0}}
+  - {name: pedals, device: /dev/input/event3}
+  - {name: rudder, device: /dev/input/event3}
+  - {name: throttle, match: {vendor: 0x044f, phys: usb-2/input0}}
+outputs: []
+"
+            ),
+            vec![
+                "Joysticks \"left\" and \"right\" are the same device",
+                "Joysticks \"pedals\" and \"rudder\" are the same device",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_axis_on_two_devices() {
+        let config: Config = serde_yaml::from_str(
+            "
+inputs:
+  - {name: stick, device: /dev/null}
+outputs:
+  - axis_id: ABS_X
+    combine_fn: LargestMagnitude
+    inputs: [{js: stick, axis: ABS_X}]
+  - axis_id: ABS_X
+    device_index: 2
+    combine_fn: LargestMagnitude
+    inputs: [{js: stick, axis: ABS_Y}]
+",
+        )
+        .unwrap();
+        assert!(check_config(&config).is_empty());
+        assert_eq!(output_devices(&config.outputs), 3);
+        assert_eq!(output_devices(&[]), 1);
+    }
+
+    #[test]
+    fn test_check_report_shape() {
+        assert_eq!(
+            check(
+                "
+inputs:
+  - {name: stick, device: /dev/null}
+outputs:
+  - axis_id: ABS_RZ
+    combine_fn: LargestMagnitude
+    inputs: [{js: stick, axis: ABS_RZ}]
+  - axis_id: BTN_TRIGGER_HAPPY1
+    combine_fn: Button
+    inputs: [{js: stick, axis: BTN_TRIGGER}]
+report:
+  axes: [X, Y, Z, Rx, Ry, Slider, Dial, Wheel, X]
+  hats: 5
+  buttons: 200
+  axis_bits: 12
+  min_rate: 100
+  max_rate: 50
+"
+            ),
+            vec![
+                "The report can have at most 8 axes, not 9",
+                "Axis X is in the report more than once",
+                "The report can have at most 4 hats, not 5",
+                "The report can have at most 128 buttons, not 200",
+                "Axes can be 8 or 16 bits, not 12",
+                "Report rates have to be above zero, and min_rate can't be above max_rate",
+                "Output ABS_RZ has no slot in the emulated joystick",
+            ]
+        );
+        assert_eq!(
+            check(
+                "
+inputs: []
+outputs:
+  - axis_id: BTN_TRIGGER_HAPPY1
+    combine_fn: Button
+    inputs: []
+report: {buttons: 16}
+"
+            ),
+            vec!["Output BTN_TRIGGER_HAPPY1 has no slot in the emulated joystick"]
+        );
+    }
 }
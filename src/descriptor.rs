@@ -0,0 +1,302 @@
+//! Builds the HID report descriptor and the matching report layout
+//! from one description of the emulated joystick, so that the two
+//! can't drift apart.
+
+// Short item prefixes from the HID spec, section 6.2.2, without the
+// size bits.
+const INPUT: u8 = 0x80;
+const COLLECTION: u8 = 0xa0;
+const END_COLLECTION: u8 = 0xc0;
+const USAGE_PAGE: u8 = 0x04;
+const LOGICAL_MINIMUM: u8 = 0x14;
+const LOGICAL_MAXIMUM: u8 = 0x24;
+const PHYSICAL_MINIMUM: u8 = 0x34;
+const PHYSICAL_MAXIMUM: u8 = 0x44;
+const UNIT: u8 = 0x64;
+const REPORT_SIZE: u8 = 0x74;
+const REPORT_COUNT: u8 = 0x94;
+const USAGE: u8 = 0x08;
+const USAGE_MINIMUM: u8 = 0x18;
+const USAGE_MAXIMUM: u8 = 0x28;
+
+const GENERIC_DESKTOP_PAGE: i32 = 0x01;
+const BUTTON_PAGE: i32 = 0x09;
+const JOYSTICK_USAGE: i32 = 0x04;
+const HAT_SWITCH_USAGE: i32 = 0x39;
+const APPLICATION_COLLECTION: i32 = 0x01;
+
+const DATA_VARIABLE_ABSOLUTE: i32 = 0x02;
+const CONSTANT: i32 = 0x01;
+const NULL_STATE: i32 = 0x40;
+const DEGREES: i32 = 0x14;
+
+const HAT_BITS: usize = 4;
+
+pub const MAX_AXES: usize = 8;
+pub const MAX_HATS: usize = 4;
+pub const MAX_BUTTONS: usize = 128;
+pub const AXIS_BITS: [usize; 2] = [8, 16];
+
+/// Generic Desktop usages that an axis can report as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AxisUsage {
+    X,
+    Y,
+    Z,
+    Rx,
+    Ry,
+    Rz,
+    Slider,
+    Dial,
+    Wheel,
+}
+
+impl AxisUsage {
+    fn id(self) -> i32 {
+        match self {
+            AxisUsage::X => 0x30,
+            AxisUsage::Y => 0x31,
+            AxisUsage::Z => 0x32,
+            AxisUsage::Rx => 0x33,
+            AxisUsage::Ry => 0x34,
+            AxisUsage::Rz => 0x35,
+            AxisUsage::Slider => 0x36,
+            AxisUsage::Dial => 0x37,
+            AxisUsage::Wheel => 0x38,
+        }
+    }
+}
+
+/// The shape of an emulated joystick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportDescription {
+    pub axes: Vec<AxisUsage>,
+    pub hats: usize,
+    pub buttons: usize,
+    pub axis_bits: usize,
+}
+
+impl Default for ReportDescription {
+    fn default() -> Self {
+        ReportDescription {
+            axes: vec![
+                AxisUsage::X,
+                AxisUsage::Y,
+                AxisUsage::Z,
+                AxisUsage::Rx,
+                AxisUsage::Ry,
+                AxisUsage::Rz,
+                AxisUsage::Dial,
+                AxisUsage::Slider,
+            ],
+            hats: 1,
+            buttons: 48,
+            axis_bits: 16,
+        }
+    }
+}
+
+/// Where a value lives in a report, in bits. HID packs fields least
+/// significant bit first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Where each part of a [`ReportDescription`] ends up in the report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportLayout {
+    pub axes: Vec<(AxisUsage, Field)>,
+    pub axis_min: i64,
+    pub axis_max: i64,
+    pub hats: Vec<Field>,
+    pub buttons: Vec<Field>,
+    /// In bytes, for the gadget's `report_length`.
+    pub length: usize,
+}
+
+impl ReportDescription {
+    pub fn layout(&self) -> ReportLayout {
+        let mut offset = 0;
+        let mut field = |size| {
+            let field = Field { offset, size };
+            offset += size;
+            field
+        };
+        let axes = self
+            .axes
+            .iter()
+            .map(|usage| (*usage, field(self.axis_bits)))
+            .collect();
+        let hats = (0..self.hats).map(|_| field(HAT_BITS)).collect();
+        let buttons = (0..self.buttons).map(|_| field(1)).collect();
+        ReportLayout {
+            axes,
+            axis_min: -(1 << (self.axis_bits - 1)) + 1,
+            axis_max: (1 << (self.axis_bits - 1)) - 1,
+            hats,
+            buttons,
+            length: (offset + 7) / 8,
+        }
+    }
+
+    pub fn descriptor(&self) -> Vec<u8> {
+        let layout = self.layout();
+        let mut d = Vec::new();
+        item(&mut d, USAGE_PAGE, GENERIC_DESKTOP_PAGE);
+        item(&mut d, USAGE, JOYSTICK_USAGE);
+        item(&mut d, COLLECTION, APPLICATION_COLLECTION);
+
+        if !self.axes.is_empty() {
+            for usage in self.axes.iter() {
+                item(&mut d, USAGE, usage.id());
+            }
+            item(&mut d, LOGICAL_MINIMUM, layout.axis_min as i32);
+            item(&mut d, LOGICAL_MAXIMUM, layout.axis_max as i32);
+            item(&mut d, REPORT_SIZE, self.axis_bits as i32);
+            item(&mut d, REPORT_COUNT, self.axes.len() as i32);
+            item(&mut d, INPUT, DATA_VARIABLE_ABSOLUTE);
+        }
+
+        if self.hats > 0 {
+            for _ in 0..self.hats {
+                item(&mut d, USAGE, HAT_SWITCH_USAGE);
+            }
+            // Eight directions, 45 degrees apart. Anything out of range
+            // means centered.
+            item(&mut d, LOGICAL_MINIMUM, 0);
+            item(&mut d, LOGICAL_MAXIMUM, 7);
+            item(&mut d, PHYSICAL_MINIMUM, 0);
+            item(&mut d, PHYSICAL_MAXIMUM, 315);
+            item(&mut d, UNIT, DEGREES);
+            item(&mut d, REPORT_SIZE, HAT_BITS as i32);
+            item(&mut d, REPORT_COUNT, self.hats as i32);
+            item(&mut d, INPUT, DATA_VARIABLE_ABSOLUTE | NULL_STATE);
+            item(&mut d, PHYSICAL_MAXIMUM, 0);
+            item(&mut d, UNIT, 0);
+        }
+
+        if self.buttons > 0 {
+            item(&mut d, USAGE_PAGE, BUTTON_PAGE);
+            item(&mut d, USAGE_MINIMUM, 1);
+            item(&mut d, USAGE_MAXIMUM, self.buttons as i32);
+            item(&mut d, LOGICAL_MINIMUM, 0);
+            item(&mut d, LOGICAL_MAXIMUM, 1);
+            item(&mut d, REPORT_SIZE, 1);
+            item(&mut d, REPORT_COUNT, self.buttons as i32);
+            item(&mut d, INPUT, DATA_VARIABLE_ABSOLUTE);
+        }
+
+        let used = self.axes.len() * self.axis_bits + self.hats * HAT_BITS + self.buttons;
+        let padding = layout.length * 8 - used;
+        if padding > 0 {
+            item(&mut d, REPORT_SIZE, 1);
+            item(&mut d, REPORT_COUNT, padding as i32);
+            item(&mut d, INPUT, CONSTANT);
+        }
+
+        d.push(END_COLLECTION);
+        d
+    }
+}
+
+/// Appends a short item with the smallest data size that holds
+/// `value`.
+fn item(descriptor: &mut Vec<u8>, prefix: u8, value: i32) {
+    let bytes = value.to_le_bytes();
+    let size = if i8::try_from(value).is_ok() {
+        1
+    } else if i16::try_from(value).is_ok() {
+        2
+    } else {
+        4
+    };
+    let size_bits = match size {
+        1 => 1,
+        2 => 2,
+        _ => 3,
+    };
+    descriptor.push(prefix | size_bits);
+    descriptor.extend_from_slice(&bytes[..size]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_layout() {
+        let layout = ReportDescription::default().layout();
+        assert_eq!(layout.length, 23);
+        assert_eq!(
+            layout.axes[1],
+            (
+                AxisUsage::Y,
+                Field {
+                    offset: 16,
+                    size: 16
+                }
+            )
+        );
+        assert_eq!(
+            layout.hats,
+            vec![Field {
+                offset: 128,
+                size: 4
+            }]
+        );
+        assert_eq!(
+            layout.buttons[47],
+            Field {
+                offset: 179,
+                size: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_8_bit_axes() {
+        let layout = ReportDescription {
+            axes: vec![AxisUsage::X, AxisUsage::Y, AxisUsage::Wheel],
+            hats: 2,
+            buttons: 128,
+            axis_bits: 8,
+        }
+        .layout();
+        assert_eq!((layout.axis_min, layout.axis_max), (-127, 127));
+        assert_eq!(
+            layout.hats[1],
+            Field {
+                offset: 28,
+                size: 4
+            }
+        );
+        assert_eq!(layout.length, 20);
+    }
+
+    #[test]
+    fn test_descriptor_with_padding() {
+        let description = ReportDescription {
+            axes: vec![AxisUsage::X, AxisUsage::Y],
+            hats: 0,
+            buttons: 3,
+            axis_bits: 16,
+        };
+        assert_eq!(description.layout().length, 5);
+        assert_eq!(
+            description.descriptor(),
+            vec![
+                0x05, 0x01, 0x09, 0x04, 0xa1, 0x01, // Joystick
+                0x09, 0x30, 0x09, 0x31, // X, Y
+                0x16, 0x01, 0x80, 0x26, 0xff, 0x7f, // -32767..32767
+                0x75, 0x10, 0x95, 0x02, 0x81, 0x02, // 2 16-bit inputs
+                0x05, 0x09, 0x19, 0x01, 0x29, 0x03, // Buttons 1..3
+                0x15, 0x00, 0x25, 0x01, // 0..1
+                0x75, 0x01, 0x95, 0x03, 0x81, 0x02, // 3 1-bit inputs
+                0x75, 0x01, 0x95, 0x05, 0x81, 0x01, // 5 bits of padding
+                0xc0,
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::config_loader::{ConfigDeviceSelector, ConfigInput};
+use evdev_rs::DeviceWrapper;
+use itertools::Itertools;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub static INPUT_DIR: &str = "/dev/input";
+
+#[derive(Error, Debug)]
+pub enum DeviceSelectorError {
+    #[error("Couldn't list input devices")]
+    ListDevices(#[from] io::Error),
+    #[error("No input device matches")]
+    NotFound,
+    #[error("More than one input device matches: {}", .0.iter().map(|path| path.display()).join(", "))]
+    Ambiguous(Vec<PathBuf>),
+}
+
+/// The parts of an evdev device that a selector can match on.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub vendor: u16,
+    pub product: u16,
+    pub name: Option<String>,
+    pub uniq: Option<String>,
+    pub phys: Option<String>,
+}
+
+impl DeviceIdentity {
+    pub fn of(device: &evdev_rs::Device) -> Self {
+        DeviceIdentity {
+            vendor: device.vendor_id(),
+            product: device.product_id(),
+            name: device.name().map(String::from),
+            uniq: device.uniq().map(String::from),
+            phys: device.phys().map(String::from),
+        }
+    }
+}
+
+fn field_matches(wanted: &Option<String>, actual: &Option<String>) -> bool {
+    match wanted {
+        Some(wanted) => actual.as_ref() == Some(wanted),
+        None => true,
+    }
+}
+
+/// Every field that's set in `selector` has to match.
+pub fn matches(selector: &ConfigDeviceSelector, identity: &DeviceIdentity) -> bool {
+    selector
+        .vendor
+        .map_or(true, |vendor| vendor == identity.vendor)
+        && selector
+            .product
+            .map_or(true, |product| product == identity.product)
+        && field_matches(&selector.name, &identity.name)
+        && field_matches(&selector.uniq, &identity.uniq)
+        && field_matches(&selector.phys, &identity.phys)
+}
+
+/// Every evdev device that we're allowed to open.
+pub fn list_devices() -> Result<Vec<(PathBuf, evdev_rs::Device)>, DeviceSelectorError> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(INPUT_DIR)? {
+        let path = entry?.path();
+        let is_event_node = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.starts_with("event"));
+        if !is_event_node {
+            continue;
+        }
+        if let Ok(device) = evdev_rs::Device::new_from_path(&path) {
+            devices.push((path, device));
+        }
+    }
+    devices.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(devices)
+}
+
+pub fn find_device(
+    selector: &ConfigDeviceSelector,
+) -> Result<(PathBuf, evdev_rs::Device), DeviceSelectorError> {
+    let mut found = list_devices()?
+        .into_iter()
+        .filter(|(_, device)| matches(selector, &DeviceIdentity::of(device)))
+        .collect::<Vec<_>>();
+    match found.len() {
+        0 => Err(DeviceSelectorError::NotFound),
+        1 => Ok(found.remove(0)),
+        _ => Err(DeviceSelectorError::Ambiguous(
+            found.into_iter().map(|(path, _)| path).collect(),
+        )),
+    }
+}
+
+/// Opens the device for `input`, either by path or by selector.
+pub fn open_input(input: &ConfigInput) -> Result<evdev_rs::Device, DeviceSelectorError> {
+    match &input.device {
+        Some(path) => Ok(evdev_rs::Device::new_from_path(Path::new(path))?),
+        None => Ok(find_device(&input.selector)?.1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t16000m(phys: &str) -> DeviceIdentity {
+        DeviceIdentity {
+            vendor: 0x044f,
+            product: 0xb10a,
+            name: Some("Thrustmaster T.16000M".to_string()),
+            uniq: None,
+            phys: Some(phys.to_string()),
+        }
+    }
+
+    fn selector(yaml: &str) -> ConfigDeviceSelector {
+        serde_yaml::from_str(yaml).expect("test selector should parse")
+    }
+
+    #[test]
+    fn test_empty_selector_matches_everything() {
+        assert!(matches(&selector("{}"), &t16000m("usb-1/input0")));
+    }
+
+    #[test]
+    fn test_ids_and_name() {
+        let left = t16000m("usb-1/input0");
+        assert!(matches(
+            &selector("{vendor: 0x044f, product: 0xb10a}"),
+            &left
+        ));
+        assert!(matches(&selector("{name: Thrustmaster T.16000M}"), &left));
+        assert!(!matches(
+            &selector("{vendor: 0x044f, product: 0xb687}"),
+            &left
+        ));
+    }
+
+    #[test]
+    fn test_phys_tells_identical_sticks_apart() {
+        let hosas = selector("{vendor: 0x044f, product: 0xb10a, phys: usb-2/input0}");
+        assert!(!matches(&hosas, &t16000m("usb-1/input0")));
+        assert!(matches(&hosas, &t16000m("usb-2/input0")));
+    }
+
+    #[test]
+    fn test_unset_field_doesnt_match() {
+        assert!(!matches(
+            &selector("{uniq: ABC123}"),
+            &t16000m("usb-1/input0")
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+use evdev_rs::enums::{EventCode, EventType};
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum EventCodeError {
+    #[error("Unknown event code {name:?}{}", suggestion_text(.suggestion))]
+    UnknownName {
+        name: String,
+        suggestion: Option<String>,
+    },
+    #[error(
+        "Bare number {0} is ambiguous, give it a type prefix like ABS_{0}, REL_{0} or BTN_{0}"
+    )]
+    BareNumber(u64),
+}
+
+fn suggestion_text(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(name) => format!(", did you mean {name}?"),
+        None => String::new(),
+    }
+}
+
+// Only the event types that get_input_axes collects and that the
+// emulated joystick can emit. BTN_ and KEY_ share EV_KEY.
+const PREFIXES: [(&str, EventType); 4] = [
+    ("ABS_", EventType::EV_ABS),
+    ("REL_", EventType::EV_REL),
+    ("KEY_", EventType::EV_KEY),
+    ("BTN_", EventType::EV_KEY),
+];
+
+/// The symbolic name of an event code, e.g. `ABS_X` or `BTN_300`.
+pub fn name(code: &EventCode) -> String {
+    match code {
+        EventCode::EV_ABS(abs) => format!("{abs:?}"),
+        EventCode::EV_REL(rel) => format!("{rel:?}"),
+        EventCode::EV_KEY(key) => format!("{key:?}"),
+        other => format!("{other:?}"),
+    }
+}
+
+fn known_codes() -> impl Iterator<Item = EventCode> {
+    [EventType::EV_ABS, EventType::EV_REL, EventType::EV_KEY]
+        .into_iter()
+        .flat_map(|ev_type| evdev_rs::EventCodeIterator::new(&ev_type))
+        .filter(|code| {
+            matches!(
+                code,
+                EventCode::EV_ABS(_) | EventCode::EV_REL(_) | EventCode::EV_KEY(_)
+            )
+        })
+}
+
+fn parse_number(number: &str) -> Option<u32> {
+    match number.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => number.parse().ok(),
+    }
+}
+
+fn from_number(name: &str) -> Option<EventCode> {
+    PREFIXES.iter().find_map(|(prefix, ev_type)| {
+        let number = parse_number(name.strip_prefix(prefix)?)?;
+        match evdev_rs::util::int_to_event_code(*ev_type as u32, number) {
+            EventCode::EV_UNK { .. } => None,
+            code => Some(code),
+        }
+    })
+}
+
+/// Parses a symbolic event code like `ABS_X` or `BTN_TRIGGER`. Codes
+/// without a name of their own can be given as a prefix and a number,
+/// e.g. `BTN_300` or `ABS_0x28`.
+pub fn parse(name: &str) -> Result<EventCode, EventCodeError> {
+    if let Some(code) = known_codes().find(|code| self::name(code) == name) {
+        return Ok(code);
+    }
+    if let Some(code) = from_number(name) {
+        return Ok(code);
+    }
+    let suggestion = known_codes()
+        .map(|code| self::name(&code))
+        .min_by_key(|candidate| strsim::levenshtein(candidate, name));
+    Err(EventCodeError::UnknownName {
+        name: name.to_string(),
+        suggestion,
+    })
+}
+
+struct EventCodeVisitor;
+
+impl<'de> Visitor<'de> for EventCodeVisitor {
+    type Value = EventCode;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an event code name like ABS_X or BTN_TRIGGER")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<EventCode, E> {
+        parse(value).map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<EventCode, E> {
+        Err(E::custom(EventCodeError::BareNumber(value)))
+    }
+}
+
+/// For use with `#[serde(deserialize_with = "...")]`.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EventCode, D::Error> {
+    deserializer.deserialize_any(EventCodeVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev_rs::enums::{EV_ABS, EV_KEY, EV_REL};
+
+    #[test]
+    fn test_names() {
+        assert_eq!(parse("ABS_X"), Ok(EventCode::EV_ABS(EV_ABS::ABS_X)));
+        assert_eq!(parse("REL_RZ"), Ok(EventCode::EV_REL(EV_REL::REL_RZ)));
+        assert_eq!(
+            parse("BTN_TRIGGER"),
+            Ok(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER))
+        );
+        assert_eq!(parse("BTN_0"), Ok(EventCode::EV_KEY(EV_KEY::BTN_0)));
+        assert_eq!(parse("BTN_300"), Ok(EventCode::EV_KEY(EV_KEY::BTN_300)));
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(parse("KEY_300"), Ok(EventCode::EV_KEY(EV_KEY::BTN_300)));
+        assert_eq!(parse("ABS_0x05"), Ok(EventCode::EV_ABS(EV_ABS::ABS_RZ)));
+        assert_eq!(parse("REL_5"), Ok(EventCode::EV_REL(EV_REL::REL_RZ)));
+    }
+
+    #[test]
+    fn test_typo_suggests_closest() {
+        assert_eq!(
+            parse("BTN_TRIGER"),
+            Err(EventCodeError::UnknownName {
+                name: "BTN_TRIGER".to_string(),
+                suggestion: Some("BTN_TRIGGER".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_deserialize_bare_number() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Wrapper {
+            #[serde(deserialize_with = "deserialize")]
+            #[allow(dead_code)]
+            code: EventCode,
+        }
+        let err = serde_yaml::from_str::<Wrapper>("code: 5").unwrap_err();
+        assert!(err.to_string().contains("ABS_5"));
+    }
+}
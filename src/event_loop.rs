@@ -0,0 +1,291 @@
+//! Runs the mux on one thread, waiting on every input device, the tick
+//! timer, if the mapping needs one, and config reloads with epoll, so that an event goes from the
+//! device through the mux without passing through any channels or
+//! waking any other threads. The mux's output still goes through its
+//! channel, which this thread drains and hands to the sinks itself
+//! after each batch of events.
+
+use crate::joystick_mux::{AxisCombineFn, JoystickMux, MuxInput, OutputAxisId, OutputState};
+use crate::reader::{self, OpenInput};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Devices are identified by their index in the list of inputs.
+const TICK: u64 = u64::MAX;
+const WAKE: u64 = u64::MAX - 1;
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// Takes ownership of a new file descriptor, so that it's closed when
+/// dropped.
+fn own(fd: libc::c_int) -> io::Result<File> {
+    Ok(unsafe { File::from_raw_fd(check(fd)?) })
+}
+
+/// Reads the 8-byte counter that timerfds and eventfds hand back. They
+/// don't have one when they haven't fired, which is fine.
+fn read_counter(file: &File) -> io::Result<()> {
+    match (&mut &*file).read(&mut [0; 8]) {
+        Err(err) if err.kind() != io::ErrorKind::WouldBlock => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = check(unsafe { libc::fcntl(fd, libc::F_GETFL) })?;
+    check(unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) })?;
+    Ok(())
+}
+
+struct Epoll(File);
+
+impl Epoll {
+    fn new() -> io::Result<Self> {
+        Ok(Epoll(own(unsafe {
+            libc::epoll_create1(libc::EPOLL_CLOEXEC)
+        })?))
+    }
+
+    fn add(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token,
+        };
+        check(unsafe { libc::epoll_ctl(self.0.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) })?;
+        Ok(())
+    }
+
+    fn remove(&self, fd: RawFd) -> io::Result<()> {
+        check(unsafe {
+            libc::epoll_ctl(
+                self.0.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                fd,
+                std::ptr::null_mut(),
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Blocks until something's ready, returning the tokens of what is.
+    fn wait(&self, events: &mut [libc::epoll_event]) -> io::Result<Vec<u64>> {
+        let ready = unsafe {
+            libc::epoll_wait(
+                self.0.as_raw_fd(),
+                events.as_mut_ptr(),
+                events.len() as libc::c_int,
+                -1,
+            )
+        };
+        match check(ready) {
+            Ok(ready) => Ok(events[..ready as usize]
+                .iter()
+                .map(|event| event.u64)
+                .collect()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Fires every so often once it's set, and never before.
+struct Timer(File);
+
+impl Timer {
+    fn new() -> io::Result<Self> {
+        Ok(Timer(own(unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        })?))
+    }
+
+    /// Fires every `interval` from now on, or stops firing if it's
+    /// `None`.
+    fn set(&self, interval: Option<Duration>) -> io::Result<()> {
+        // An all-zero interval disarms the timer.
+        let interval = interval.unwrap_or(Duration::ZERO);
+        let interval = libc::timespec {
+            tv_sec: interval.as_secs() as libc::time_t,
+            tv_nsec: interval.subsec_nanos() as libc::c_long,
+        };
+        let spec = libc::itimerspec {
+            it_interval: interval,
+            it_value: interval,
+        };
+        check(unsafe {
+            libc::timerfd_settime(self.0.as_raw_fd(), 0, &spec, std::ptr::null_mut())
+        })?;
+        Ok(())
+    }
+}
+
+/// Wakes up the loop from another thread.
+#[derive(Clone)]
+struct Waker(Arc<File>);
+
+impl Waker {
+    fn new() -> io::Result<Self> {
+        Ok(Waker(Arc::new(own(unsafe {
+            libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC)
+        })?)))
+    }
+
+    fn wake(&self) {
+        (&mut &*self.0)
+            .write_all(&1u64.to_ne_bytes())
+            .expect("Failed to wake event loop");
+    }
+}
+
+/// Starts waiting on `input` and tells the mux where it is now.
+fn start(epoll: &Epoll, slot: usize, input: &OpenInput, mux: &mut JoystickMux) -> Result<()> {
+    let fd = input.device.file().as_raw_fd();
+    set_nonblocking(fd).context("Failed to make device non-blocking")?;
+    epoll
+        .add(fd, slot as u64)
+        .context("Failed to wait on device")?;
+    for update in input.current_state() {
+        mux.handle(MuxInput::Seed(update));
+    }
+    Ok(())
+}
+
+/// Runs until something fails. `mux` has to send its output to
+/// `outputs`, which has to be unbounded since nothing else will drain
+/// it, and each output is passed to `send`.
+pub fn run(
+    inputs: Vec<OpenInput>,
+    mut mux: JoystickMux,
+    outputs: crossbeam_channel::Receiver<OutputState>,
+    axes_r: crossbeam_channel::Receiver<HashMap<OutputAxisId, AxisCombineFn>>,
+    tick: Duration,
+    mut send: impl FnMut(OutputState) -> Result<()>,
+) -> Result<()> {
+    let epoll = Epoll::new().context("Failed to create epoll")?;
+    let waker = Waker::new().context("Failed to create eventfd")?;
+    epoll.add(waker.0.as_raw_fd(), WAKE)?;
+    let timer = Timer::new().context("Failed to create timerfd")?;
+    epoll.add(timer.0.as_raw_fd(), TICK)?;
+    // Waking up for ticks that can't change anything only adds jitter,
+    // so the timer only runs while the mapping has something timed.
+    let mut ticking = mux.needs_ticks();
+    timer.set(ticking.then_some(tick))?;
+
+    // Reloads and reconnects block, so they happen on other threads and
+    // come back through these.
+    let (reload_s, reload_r) = crossbeam_channel::unbounded();
+    let (reconnect_s, reconnect_r) = crossbeam_channel::unbounded::<(usize, OpenInput)>();
+    let reload_waker = waker.clone();
+    thread::spawn(move || {
+        for axes in axes_r {
+            if reload_s.send(axes).is_err() {
+                return;
+            }
+            reload_waker.wake();
+        }
+    });
+
+    let mut slots = Vec::new();
+    for (slot, input) in inputs.into_iter().enumerate() {
+        start(&epoll, slot, &input, &mut mux)?;
+        slots.push(Some(input));
+    }
+
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 16];
+    loop {
+        for token in epoll.wait(&mut events).context("Failed to wait")? {
+            match token {
+                TICK => {
+                    read_counter(&timer.0)?;
+                    mux.handle(MuxInput::Tick(reader::now()));
+                }
+                WAKE => {
+                    read_counter(&waker.0)?;
+                    for axes in reload_r.try_iter() {
+                        mux.swap_axes(axes);
+                        mux.send_output();
+                    }
+                    if ticking != mux.needs_ticks() {
+                        ticking = !ticking;
+                        timer.set(ticking.then_some(tick))?;
+                    }
+                    for (slot, input) in reconnect_r.try_iter() {
+                        eprintln!("Reconnected {}", input.input.name);
+                        start(&epoll, slot, &input, &mut mux)?;
+                        slots[slot] = Some(input);
+                    }
+                }
+                slot => {
+                    let slot = slot as usize;
+                    // It may have been lost earlier in this batch.
+                    let input = match slots[slot].as_mut() {
+                        Some(input) => input,
+                        None => continue,
+                    };
+                    let err = input.read_events(|update| mux.handle(MuxInput::Update(update)));
+                    if reader::is_lost(&err) {
+                        let mut input = slots[slot].take().expect("Slot was just read");
+                        eprintln!("Lost {}: {err}", input.input.name);
+                        // The device may already be closed, in which
+                        // case epoll has forgotten it already.
+                        let _ = epoll.remove(input.device.file().as_raw_fd());
+                        mux.handle(MuxInput::Disconnected(input.id));
+                        let reconnect_s = reconnect_s.clone();
+                        let waker = waker.clone();
+                        thread::spawn(move || {
+                            input.reconnect_retrying();
+                            if reconnect_s.send((slot, input)).is_ok() {
+                                waker.wake();
+                            }
+                        });
+                    }
+                }
+            }
+        }
+        for output in outputs.try_iter() {
+            send(output)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wake_and_tick() {
+        let epoll = Epoll::new().unwrap();
+        let waker = Waker::new().unwrap();
+        epoll.add(waker.0.as_raw_fd(), WAKE).unwrap();
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 4];
+
+        waker.clone().wake();
+        assert_eq!(epoll.wait(&mut events).unwrap(), vec![WAKE]);
+        read_counter(&waker.0).unwrap();
+
+        let timer = Timer::new().unwrap();
+        epoll.add(timer.0.as_raw_fd(), TICK).unwrap();
+        timer.set(Some(Duration::from_millis(1))).unwrap();
+        assert_eq!(epoll.wait(&mut events).unwrap(), vec![TICK]);
+
+        timer.set(None).unwrap();
+        read_counter(&timer.0).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        waker.wake();
+        assert_eq!(epoll.wait(&mut events).unwrap(), vec![WAKE]);
+    }
+}
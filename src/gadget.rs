@@ -1,4 +1,5 @@
-use anyhow::{Context, Result};
+use crate::descriptor::ReportDescription;
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 use std::fs;
 use std::io;
@@ -7,7 +8,47 @@ use std::path::PathBuf;
 
 static GADGET_DIR: &str = "/sys/kernel/config/usb_gadget/composite_joystick";
 
-pub fn init_gadget() -> Result<()> {
+/// Each joystick takes an endpoint, and the controllers on the boards
+/// this runs on don't have many more than this to spare.
+pub const MAX_DEVICES: usize = 4;
+
+fn function_name(index: usize) -> String {
+    format!("hid.usb{index}")
+}
+
+fn init_function(index: usize, description: &ReportDescription) -> Result<()> {
+    let function = PathBuf::from(GADGET_DIR)
+        .join("functions")
+        .join(function_name(index));
+    fs::create_dir_all(&function).context("Failed to create function")?;
+    fs::write(function.join("protocol"), "1").context("Failed to set protocol")?;
+    fs::write(function.join("subclass"), "1").context("Failed to set subclass")?;
+    fs::write(
+        function.join("report_length"),
+        description.layout().length.to_string(),
+    )
+    .context("Failed to set report length")?;
+    fs::write(function.join("report_desc"), description.descriptor())
+        .context("Failed to set report descriptor")?;
+
+    std::os::unix::fs::symlink(
+        &function,
+        PathBuf::from(GADGET_DIR)
+            .join("configs")
+            .join("c.1")
+            .join(function_name(index)),
+    )
+    .context("Failed to symlink function into config dir")?;
+    Ok(())
+}
+
+/// Sets up a gadget with `devices` joysticks shaped like `description`
+/// on it, which show up as /dev/hidg0 through /dev/hidg{devices - 1}.
+pub fn init_gadget(devices: usize, description: &ReportDescription) -> Result<()> {
+    if devices == 0 || devices > MAX_DEVICES {
+        bail!("Can emulate 1 to {MAX_DEVICES} joysticks, not {devices}");
+    }
+
     // Make gadget dir
     fs::create_dir_all(PathBuf::from(GADGET_DIR)).context("Failed to create gadget dir")?;
 
@@ -49,44 +90,6 @@ pub fn init_gadget() -> Result<()> {
     )
     .context("Failed to write serial")?;
 
-    // Create function
-    fs::create_dir_all(PathBuf::from(GADGET_DIR).join("functions").join("hid.usb0"))
-        .context("Failed to create function")?;
-    fs::write(
-        PathBuf::from(GADGET_DIR)
-            .join("functions")
-            .join("hid.usb0")
-            .join("protocol"),
-        "1",
-    )
-    .context("Failed to set protocol")?;
-    fs::write(
-        PathBuf::from(GADGET_DIR)
-            .join("functions")
-            .join("hid.usb0")
-            .join("subclass"),
-        "1",
-    )
-    .context("Failed to set subclass")?;
-    fs::write(
-        PathBuf::from(GADGET_DIR)
-            .join("functions")
-            .join("hid.usb0")
-            .join("report_length"),
-        "22",
-    )
-    .context("Failed to set report length")?;
-    let descriptor = include_str!("descriptor.hex");
-    let descriptor = descriptor.replace(' ', "");
-    fs::write(
-        PathBuf::from(GADGET_DIR)
-            .join("functions")
-            .join("hid.usb0")
-            .join("report_desc"),
-        hex::decode(descriptor)?,
-    )
-    .context("Failed to set report descriptor")?;
-
     // Create config
     fs::create_dir_all(PathBuf::from(GADGET_DIR).join("configs").join("c.1"))
         .context("Failed to create config dir")?;
@@ -117,14 +120,9 @@ pub fn init_gadget() -> Result<()> {
     )
     .context("Failed to write config string")?;
 
-    std::os::unix::fs::symlink(
-        PathBuf::from(GADGET_DIR).join("functions").join("hid.usb0"),
-        PathBuf::from(GADGET_DIR)
-            .join("configs")
-            .join("c.1")
-            .join("hid.usb0"),
-    )
-    .context("Failed to symlink function into config dir")?;
+    for index in 0..devices {
+        init_function(index, description)?;
+    }
 
     // write UDC
     let udcs = PathBuf::from("/sys/class/udc")
@@ -151,13 +149,21 @@ pub fn uninit_gadget() -> Result<()> {
     }
 
     fs::write(PathBuf::from(GADGET_DIR).join("UDC"), "").context("Failed to clear UDC")?;
-    fs::remove_file(
-        PathBuf::from(GADGET_DIR)
-            .join("configs")
-            .join("c.1")
-            .join("hid.usb0"),
-    )
-    .context("Failed to remove gadget symlink")?;
+    let functions = PathBuf::from(GADGET_DIR)
+        .join("functions")
+        .read_dir()
+        .context("Failed to read gadget functions")?
+        .collect::<Result<Vec<_>, io::Error>>()
+        .context("Failed to read gadget function")?;
+    for function in functions.iter() {
+        fs::remove_file(
+            PathBuf::from(GADGET_DIR)
+                .join("configs")
+                .join("c.1")
+                .join(function.file_name()),
+        )
+        .context("Failed to remove gadget symlink")?;
+    }
     fs::remove_dir(
         PathBuf::from(GADGET_DIR)
             .join("configs")
@@ -168,8 +174,9 @@ pub fn uninit_gadget() -> Result<()> {
     .context("Failed to remove config strings dir")?;
     fs::remove_dir(PathBuf::from(GADGET_DIR).join("configs").join("c.1"))
         .context("Failed to remove config")?;
-    fs::remove_dir(PathBuf::from(GADGET_DIR).join("functions").join("hid.usb0"))
-        .context("Failed to remove function")?;
+    for function in functions.iter() {
+        fs::remove_dir(function.path()).context("Failed to remove function")?;
+    }
     fs::remove_dir(PathBuf::from(GADGET_DIR).join("strings").join("0x409"))
         .context("Failed to remove strings dir")?;
     fs::remove_dir(PathBuf::from(GADGET_DIR)).context("Failed to remove gadget")?;
@@ -177,6 +184,23 @@ pub fn uninit_gadget() -> Result<()> {
     Ok(())
 }
 
-pub fn get_gadget_device() -> io::Result<fs::File> {
-    fs::File::create("/dev/hidg0")
+/// Makes sure that joystick `index` was set up with the same shape as
+/// `description`, since the host would misread our reports otherwise.
+pub fn check_function(index: usize, description: &ReportDescription) -> Result<()> {
+    let path = PathBuf::from(GADGET_DIR)
+        .join("functions")
+        .join(function_name(index))
+        .join("report_desc");
+    let descriptor =
+        fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if descriptor != description.descriptor() {
+        anyhow::bail!(
+            "Gadget device {index} has a different report than the config, rerun init with --config"
+        );
+    }
+    Ok(())
+}
+
+pub fn get_gadget_device(index: usize) -> io::Result<fs::File> {
+    fs::File::create(format!("/dev/hidg{index}"))
 }
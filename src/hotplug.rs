@@ -0,0 +1,82 @@
+use crate::config_loader::{ConfigDeviceSelector, ConfigInput};
+use crate::device_selector::{self, DeviceSelectorError};
+use anyhow::{Context, Result};
+use inotify::{Inotify, WatchMask};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// udev creates the /dev/input/by-id link before it's done setting up
+// permissions on the device node, so the first open can fail.
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Blocks until the device for `input` can be opened again.
+pub fn wait_for_input(input: &ConfigInput) -> Result<evdev_rs::Device> {
+    match &input.device {
+        Some(path) => wait_for_device(Path::new(path)),
+        None => wait_for_match(&input.selector),
+    }
+}
+
+/// Blocks until the device at `path` can be opened again.
+pub fn wait_for_device(path: &Path) -> Result<evdev_rs::Device> {
+    let mut inotify = Inotify::init().context("Failed to initialize inotify")?;
+    let mut buffer = [0; 4096];
+    loop {
+        // udev removes /dev/input/by-id when the last device in it goes
+        // away, so watch the closest directory that still exists and
+        // look again whenever anything shows up in it.
+        let dir = path
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.is_dir())
+            .context("Device path has no existing parent directory")?;
+        let wd = inotify
+            .watches()
+            .add(dir, WatchMask::CREATE | WatchMask::MOVED_TO)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+        if path.exists() {
+            match evdev_rs::Device::new_from_path(path) {
+                Ok(device) => return Ok(device),
+                Err(_) => thread::sleep(OPEN_RETRY_DELAY),
+            }
+        } else {
+            inotify
+                .read_events_blocking(&mut buffer)
+                .context("Failed to read inotify events")?;
+        }
+
+        // The directory might have been removed already, which removes
+        // the watch along with it.
+        let _ = inotify.watches().remove(wd);
+    }
+}
+
+/// Blocks until a device matches `selector`, failing if more than one
+/// does.
+pub fn wait_for_match(selector: &ConfigDeviceSelector) -> Result<evdev_rs::Device> {
+    let mut inotify = Inotify::init().context("Failed to initialize inotify")?;
+    let mut buffer = [0; 4096];
+    // New device nodes show up with CREATE, and become readable to us
+    // with ATTRIB once udev has set their permissions.
+    inotify
+        .watches()
+        .add(
+            device_selector::INPUT_DIR,
+            WatchMask::CREATE | WatchMask::ATTRIB,
+        )
+        .with_context(|| format!("Failed to watch {}", device_selector::INPUT_DIR))?;
+    loop {
+        match device_selector::find_device(selector) {
+            Ok((_, device)) => return Ok(device),
+            Err(DeviceSelectorError::NotFound) => {}
+            // Another matching device showing up won't go away by
+            // itself, and waiting for it would hang without a word.
+            Err(err) => return Err(err.into()),
+        }
+        inotify
+            .read_events_blocking(&mut buffer)
+            .context("Failed to read inotify events")?;
+    }
+}
@@ -1,71 +1,314 @@
-use evdev_rs::enums::EventCode;
-use evdev_rs::InputEvent;
+use crate::reader;
+use evdev_rs::enums::{EventCode, EV_SYN};
+use evdev_rs::{InputEvent, TimeVal};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::time::Duration;
 
-use thiserror::Error;
+pub const OUTPUT_UPPER_BOUND: i64 = 32767;
+pub const OUTPUT_LOWER_BOUND: i64 = -32767;
 
-const OUTPUT_UPPER_BOUND: i64 = 32767;
-const OUTPUT_LOWER_BOUND: i64 = -32767;
-
-#[derive(Error, Debug)]
-pub enum JoystickMuxError {}
+/// How long a [`Press::Tap`] input reads as pressed once the tap is
+/// recognized, since the button's already been let go by then.
+pub const TAP_LENGTH: Duration = Duration::from_millis(50);
 
 #[derive(Debug, Hash, PartialEq, Eq, Copy, Clone)]
 pub struct JoystickId(pub u16);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ButtonMode {
     NonZero,
     Positive,
     Negative,
 }
 
-#[derive(Debug, Clone)]
+/// What a Button output does when its inputs are pressed and released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonBehavior {
+    /// Pressed while the inputs are.
+    Follow,
+    /// Each press turns it on or off.
+    Toggle,
+    /// Pressed for this long each time the inputs are pressed or
+    /// released, for switches that stay where they're put.
+    Pulse(Duration),
+    /// Pressed for this long each time the inputs are released.
+    OnRelease(Duration),
+    /// Pressed for `on` and released for `off`, over and over, while the
+    /// inputs are pressed. Needs the mux to get [`MuxInput::Tick`]s.
+    Turbo { on: Duration, off: Duration },
+}
+
+/// Where a Button output with a [`ButtonBehavior`] other than `Follow`
+/// has got to.
+#[derive(Debug, Default)]
+struct ButtonState {
+    /// Whether its inputs were pressed last time we looked.
+    pressed: bool,
+    /// Whether it's toggled on.
+    on: bool,
+    pulse_until: Option<Duration>,
+    /// When the inputs were pressed, if it's autofiring.
+    turbo_since: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriorityMode {
+    /// The first input that's active.
+    Ranked,
+    /// The active input that moved most recently.
+    LastMoved,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum AxisCombineFn {
     LargestMagnitude {
         inputs: Vec<InputAxis>,
     },
     Button {
         mode: ButtonMode,
+        behavior: ButtonBehavior,
+        inputs: Vec<InputAxis>,
+    },
+    /// Adds up the inputs, each multiplied by its weight.
+    Sum {
+        inputs: Vec<(InputAxis, f64)>,
+    },
+    /// Passes one input through. An input is active when it's further
+    /// from center than its threshold, in output units and before its
+    /// response is applied; if none are, the first input is used.
+    Priority {
+        mode: PriorityMode,
+        inputs: Vec<(InputAxis, i64)>,
+    },
+    /// Pressed while every input is. With `suppress`, once they've all
+    /// been pressed together none of them count anywhere else until
+    /// they've all been let go.
+    Chord {
+        suppress: bool,
         inputs: Vec<InputAxis>,
     },
 }
 
+impl AxisCombineFn {
+    pub fn inputs(&self) -> Vec<&InputAxis> {
+        match self {
+            AxisCombineFn::LargestMagnitude { inputs }
+            | AxisCombineFn::Button { inputs, .. }
+            | AxisCombineFn::Chord { inputs, .. } => inputs.iter().collect(),
+            AxisCombineFn::Sum { inputs } => inputs.iter().map(|(input, _)| input).collect(),
+            AxisCombineFn::Priority { inputs, .. } => {
+                inputs.iter().map(|(input, _)| input).collect()
+            }
+        }
+    }
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, Copy, Clone)]
 pub struct InputAxisId {
     pub joystick: JoystickId,
     pub axis: EventCode,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+pub enum Curve {
+    Linear,
+    /// Gentler near center, the larger the parameter the gentler.
+    Exponential(f64),
+    /// Gentler near center and near the ends, blended with linear by
+    /// the parameter from 0 to 1.
+    S(f64),
+    /// Points mapping distance from center to output, both from 0 to 1,
+    /// sorted by input. Straight lines in between, and from (0, 0) to
+    /// the first point.
+    Table(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    fn apply(&self, x: f64) -> f64 {
+        match self {
+            Curve::Linear => x,
+            Curve::Exponential(k) if k.abs() < f64::EPSILON => x,
+            Curve::Exponential(k) => (k * x).exp_m1() / k.exp_m1(),
+            Curve::S(k) => (1.0 - k) * x + k * x * x * (3.0 - 2.0 * x),
+            Curve::Table(points) => {
+                let mut previous = (0.0, 0.0);
+                for &(px, py) in points.iter() {
+                    if x <= px {
+                        let (qx, qy) = previous;
+                        if (px - qx).abs() < f64::EPSILON {
+                            return py;
+                        }
+                        return qy + (x - qx) * (py - qy) / (px - qx);
+                    }
+                    previous = (px, py);
+                }
+                previous.1
+            }
+        }
+    }
+}
+
+/// How an input is shaped before it's combined. `deadzone` and
+/// `saturation` are fractions of the distance from center to either
+/// end: anything closer to center than `deadzone` is centered, and
+/// anything further than `saturation` is all the way over.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Response {
+    pub deadzone: f64,
+    pub saturation: f64,
+    pub curve: Curve,
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Response {
+            deadzone: 0.0,
+            saturation: 1.0,
+            curve: Curve::Linear,
+        }
+    }
+}
+
+impl Response {
+    /// Shapes `value`, which goes from -1 to 1.
+    pub fn apply(&self, value: f64) -> f64 {
+        let distance = value.abs();
+        if distance <= self.deadzone {
+            return 0.0;
+        }
+        let distance = ((distance - self.deadzone) / (self.saturation - self.deadzone)).min(1.0);
+        value.signum() * self.curve.apply(distance)
+    }
+}
+
+/// What a relative axis reads as in between its events.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RelMode {
+    /// The last value it sent, until it sends another.
+    Hold,
+    /// The last value it sent, or zero once it's been quiet for this
+    /// long. Needs the mux to get [`MuxInput::Tick`]s.
+    Timeout(Duration),
+    /// Everything it's sent added up, times this scale, starting from
+    /// center and stopping at its bounds.
+    Integrate(f64),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct InputAxis {
     pub id: InputAxisId,
     pub lower_bound: i64,
     pub upper_bound: i64,
+    /// The resting value, if it's been calibrated. Values on either
+    /// side of it are scaled separately. Otherwise the middle of the
+    /// bounds is center.
+    pub center: Option<i64>,
+    pub rel_mode: RelMode,
+    pub response: Response,
+    /// The layer this input only counts in. Inputs without one count
+    /// whenever the active layer doesn't use them.
+    pub layer: Option<LayerId>,
+    /// The way of pressing the button that this input counts, if it
+    /// doesn't just follow the button.
+    pub press: Option<Press>,
+}
+
+/// A way of pressing a button, so that one button can drive several
+/// outputs.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Press {
+    /// Let go before it counts as held, and not pressed again soon
+    /// enough to be a double tap. Reads as pressed for [`TAP_LENGTH`]
+    /// once that's certain.
+    Tap,
+    /// Held for at least this long. Reads as pressed from then until
+    /// it's let go.
+    Hold(Duration),
+    /// Pressed again within this long of letting go. Reads as pressed
+    /// while it's held the second time.
+    DoubleTap(Duration),
+}
+
+/// How far through a [`Press`] a button is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Up,
+    /// Down since then, but not for long enough to be held.
+    Down(Duration),
+    Held,
+    /// Tapped then, and might be tapped again.
+    Tapped(Duration),
+    DoubleTapped,
+}
+
+#[derive(Debug)]
+struct PressState {
+    phase: Phase,
+    tap_until: Option<Duration>,
+}
+
+impl InputAxis {
+    /// An input that goes from `lower_bound` to `upper_bound`, and is
+    /// otherwise used as it is.
+    pub fn new(id: InputAxisId, lower_bound: i64, upper_bound: i64) -> Self {
+        InputAxis {
+            id,
+            lower_bound,
+            upper_bound,
+            center: None,
+            rel_mode: RelMode::Hold,
+            response: Response::default(),
+            layer: None,
+            press: None,
+        }
+    }
 }
 
 impl std::ops::Neg for InputAxis {
     type Output = Self;
     fn neg(self) -> Self::Output {
         Self {
-            id: self.id,
             lower_bound: self.upper_bound,
             upper_bound: self.lower_bound,
+            ..self
         }
     }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Copy, Clone)]
-pub struct OutputAxisId(pub EventCode);
+pub struct LayerId(pub usize);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LayerMode {
+    /// Active while the shift input is held.
+    Momentary,
+    /// Each press of the shift input turns it on or off.
+    Latching,
+}
+
+/// A set of alternative bindings, switched to with a shift input.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Layer {
+    pub shift: InputAxisId,
+    pub mode: LayerMode,
+}
+
+/// An axis or button on one of the emulated joysticks. `device` is
+/// the index of the joystick, i.e. /dev/hidg{device}.
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone)]
+pub struct OutputAxisId {
+    pub device: usize,
+    pub axis: EventCode,
+}
 
 impl Ord for OutputAxisId {
     fn cmp(&self, other: &Self) -> Ordering {
-        let OutputAxisId(self_code) = &self;
-        let OutputAxisId(other_code) = &other;
-        evdev_rs::util::event_code_to_int(self_code)
-            .cmp(&evdev_rs::util::event_code_to_int(other_code))
+        self.device.cmp(&other.device).then_with(|| {
+            evdev_rs::util::event_code_to_int(&self.axis)
+                .cmp(&evdev_rs::util::event_code_to_int(&other.axis))
+        })
     }
 }
 
@@ -80,6 +323,50 @@ pub struct JoystickMux {
     axis_states: HashMap<InputAxisId, InputEvent>,
     axes: HashMap<OutputAxisId, AxisCombineFn>,
     output_s: Option<crossbeam_channel::Sender<OutputState>>,
+    auto_range: bool,
+    /// The lowest and highest value of each input, for auto-ranging.
+    seen: HashMap<InputAxisId, (i64, i64)>,
+    /// When each input last sent an event, for [`RelMode::Timeout`].
+    touched: HashMap<InputAxisId, Duration>,
+    /// Where each [`RelMode::Integrate`] input has added up to, in its
+    /// own units.
+    positions: HashMap<InputAxisId, f64>,
+    /// The latest time it's heard of, from an event's timestamp or a
+    /// tick. Every time in the mux is on the clock input events are
+    /// stamped with, see [`crate::reader::now`].
+    now: Duration,
+    rate: ReportRate,
+    /// The axes of the last report sent, and when it was sent.
+    sent: Vec<(OutputAxisId, i64)>,
+    sent_at: Option<Duration>,
+    /// Whether a change is waiting for [`ReportRate::min_interval`] to
+    /// pass.
+    held: bool,
+    layers: Vec<Layer>,
+    /// The layers that are on, the one in charge last.
+    active_layers: Vec<LayerId>,
+    /// The layer that was in charge when each button that's down was
+    /// pressed. It stays with that layer until it's released, even if
+    /// the layer changes in between.
+    engaged: HashMap<InputAxisId, Option<LayerId>>,
+    buttons: HashMap<OutputAxisId, ButtonState>,
+    /// Buttons that some input counts a [`Press`] of.
+    presses: HashMap<InputAxisId, PressState>,
+    /// Chords that are suppressing their inputs.
+    chords: HashSet<OutputAxisId>,
+}
+
+/// Limits on how often the mux sends reports. Reports that wouldn't
+/// change anything are never sent unless they're keepalives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReportRate {
+    /// Resend the current state whenever nothing has been sent for this
+    /// long.
+    pub keepalive: Option<Duration>,
+    /// Don't send reports any closer together than this. Changes in
+    /// between are sent together on the first report or tick after it
+    /// has passed.
+    pub min_interval: Option<Duration>,
 }
 
 #[derive(Debug)]
@@ -88,15 +375,36 @@ pub struct AxisUpdate {
     pub event: InputEvent,
 }
 
+#[derive(Debug)]
+pub enum MuxInput {
+    Update(AxisUpdate),
+    /// Where an input stood when its joystick was opened, from
+    /// [`crate::reader::OpenInput::current_state`]. It sets the input
+    /// like an `Update`, but isn't a press or release of it, and the
+    /// report it ends with isn't stamped.
+    Seed(AxisUpdate),
+    /// The joystick went away; its inputs go back to neutral until it
+    /// comes back.
+    Disconnected(JoystickId),
+    /// Time has passed, and it's now this on the clock input events are
+    /// stamped with. Inputs with a [`RelMode::Timeout`] go back to zero
+    /// on the first tick after they time out.
+    Tick(Duration),
+}
+
 #[derive(Debug, PartialEq)]
 pub struct OutputState {
     pub axes: Vec<(OutputAxisId, i64)>,
+    /// When the input event that this is the result of happened, for
+    /// measuring latency.
+    pub time: Option<TimeVal>,
 }
 
 impl OutputState {
     pub fn new(axes: impl Iterator<Item = (OutputAxisId, i64)>) -> Self {
         let mut result = OutputState {
             axes: axes.into_iter().collect(),
+            time: None,
         };
         result.sort();
         result
@@ -109,9 +417,8 @@ impl OutputState {
 
 impl fmt::Display for OutputState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for (axis, value) in self.axes.iter() {
-            let OutputAxisId(code) = axis;
-            write!(f, "{code}: {value}\t")?;
+        for (OutputAxisId { device, axis }, value) in self.axes.iter() {
+            write!(f, "{device}/{axis}: {value}\t")?;
         }
         Ok(())
     }
@@ -123,43 +430,598 @@ impl JoystickMux {
             axis_states: HashMap::new(),
             axes: HashMap::new(),
             output_s,
+            auto_range: false,
+            seen: HashMap::new(),
+            touched: HashMap::new(),
+            positions: HashMap::new(),
+            now: Duration::ZERO,
+            rate: ReportRate::default(),
+            sent: Vec::new(),
+            sent_at: None,
+            held: false,
+            layers: Vec::new(),
+            active_layers: Vec::new(),
+            engaged: HashMap::new(),
+            buttons: HashMap::new(),
+            presses: HashMap::new(),
+            chords: HashSet::new(),
         }
     }
 
+    /// Sets the layers that inputs' [`InputAxis::layer`] refer to.
+    pub fn set_layers(&mut self, layers: Vec<Layer>) {
+        self.layers = layers;
+        self.active_layers.clear();
+    }
+
+    pub fn set_report_rate(&mut self, rate: ReportRate) {
+        self.rate = rate;
+    }
+
+    /// Widens each input's bounds whenever it goes past them, for
+    /// devices that don't report their range accurately.
+    pub fn set_auto_range(&mut self, auto_range: bool) {
+        self.auto_range = auto_range;
+    }
+
     pub fn configure_axis(&mut self, output_axis: OutputAxisId, combine_fn: AxisCombineFn) {
         self.axes.insert(output_axis, combine_fn);
     }
 
+    /// Replaces every output at once, keeping the current state of the
+    /// inputs. Outputs that changed start over, as if their inputs had
+    /// been down or up all along. Returns the old outputs.
+    pub fn swap_axes(
+        &mut self,
+        axes: HashMap<OutputAxisId, AxisCombineFn>,
+    ) -> HashMap<OutputAxisId, AxisCombineFn> {
+        let old = std::mem::replace(&mut self.axes, axes);
+        let changed = old
+            .keys()
+            .chain(self.axes.keys())
+            .filter(|id| old.get(id) != self.axes.get(id))
+            .copied()
+            .collect::<HashSet<_>>();
+        for id in changed.iter() {
+            self.buttons.remove(id);
+            self.chords.remove(id);
+        }
+        // The new config might count presses of these inputs
+        // differently, or not at all.
+        let inputs = changed
+            .iter()
+            .flat_map(|id| old.get(id).into_iter().chain(self.axes.get(id)))
+            .flat_map(|combine_fn| combine_fn.inputs())
+            .map(|input| input.id)
+            .collect::<HashSet<_>>();
+        self.presses.retain(|id, _| !inputs.contains(id));
+        self.update_chords();
+        self.update_buttons(false);
+        old
+    }
+
     pub fn update(&mut self, update: AxisUpdate) {
+        self.apply(update, true);
+    }
+
+    /// Applies `update`, which is a live event if `live` is set and a
+    /// [`MuxInput::Seed`] otherwise.
+    fn apply(&mut self, update: AxisUpdate, live: bool) {
+        if live {
+            // Events from different devices can arrive a little out of
+            // order, and time doesn't go backwards for them.
+            self.now = self.now.max(reader::timestamp(&update.event.time));
+        }
         match update.event.event_code {
-            EventCode::EV_SYN(_) => self.send_output(),
+            EventCode::EV_SYN(EV_SYN::SYN_REPORT) => self.report(OutputState {
+                time: live.then_some(update.event.time),
+                ..self.output()
+            }),
+            // In particular SYN_DROPPED, which means the state is
+            // wrong until the reader resyncs and sends SYN_REPORT.
+            EventCode::EV_SYN(_) => {}
             code => {
-                self.axis_states.insert(
-                    InputAxisId {
-                        joystick: update.joystick,
-                        axis: code,
-                    },
-                    update.event,
+                if self.auto_range {
+                    let value = i64::from(update.event.value);
+                    let (low, high) = self
+                        .seen
+                        .entry(InputAxisId {
+                            joystick: update.joystick,
+                            axis: code,
+                        })
+                        .or_insert((value, value));
+                    *low = (*low).min(value);
+                    *high = (*high).max(value);
+                }
+                let id = InputAxisId {
+                    joystick: update.joystick,
+                    axis: code,
+                };
+                // Where an input already is when its device is opened
+                // isn't it moving, or a press of it.
+                if live {
+                    self.touched.insert(id, self.now);
+                    self.shift(&id, update.event.value);
+                }
+                if let EventCode::EV_KEY(_) = code {
+                    if live {
+                        self.press(id, update.event.value != 0);
+                    }
+                    if update.event.value == 0 {
+                        self.engaged.remove(&id);
+                    } else {
+                        let layer = self.active_layer();
+                        self.engaged.entry(id).or_insert(layer);
+                    }
+                }
+                if let Some((scale, (lower, upper), start)) = self.integrator(&id) {
+                    let position = self.positions.entry(id).or_insert(start as f64);
+                    *position = (*position + f64::from(update.event.value) * scale)
+                        .clamp(lower.min(upper) as f64, lower.max(upper) as f64);
+                }
+                self.axis_states.insert(id, update.event);
+                self.update_chords();
+                self.update_buttons(live);
+            }
+        }
+    }
+
+    /// How the first input that integrates `id` does it, if any do: its
+    /// scale, its bounds and where it starts.
+    fn integrator(&self, id: &InputAxisId) -> Option<(f64, (i64, i64), i64)> {
+        self.axes
+            .values()
+            .flat_map(|combine_fn| combine_fn.inputs())
+            .find_map(|input| match input.rel_mode {
+                RelMode::Integrate(scale) if input.id == *id => {
+                    let (lower, upper) = self.bounds(input);
+                    let start = input.center.unwrap_or((lower + upper) / 2);
+                    Some((scale, (lower, upper), start))
+                }
+                _ => None,
+            })
+    }
+
+    /// Turns layers on and off if `id` is their shift input.
+    fn shift(&mut self, id: &InputAxisId, value: i32) {
+        for (index, layer) in self.layers.iter().enumerate() {
+            if layer.shift != *id {
+                continue;
+            }
+            let index = LayerId(index);
+            let active = self.active_layers.contains(&index);
+            let on = match layer.mode {
+                LayerMode::Momentary => value != 0,
+                // Ignore key repeats.
+                LayerMode::Latching if value == 1 => !active,
+                LayerMode::Latching => active,
+            };
+            if on && !active {
+                self.active_layers.push(index);
+            } else if !on && active {
+                self.active_layers.retain(|active| *active != index);
+            }
+        }
+    }
+
+    fn active_layer(&self) -> Option<LayerId> {
+        self.active_layers.last().copied()
+    }
+
+    /// Whether `input` counts right now, going by its layer.
+    fn in_layer(&self, input: &InputAxis) -> bool {
+        let layer = match self.engaged.get(&input.id) {
+            Some(layer) => *layer,
+            None => self.active_layer(),
+        };
+        match (input.layer, layer) {
+            (Some(own), layer) => layer == Some(own),
+            (None, None) => true,
+            // Inputs fall through to the base layer when the active
+            // layer doesn't use them.
+            (None, Some(layer)) => !self
+                .axes
+                .values()
+                .flat_map(|combine_fn| combine_fn.inputs())
+                .any(|other| other.id == input.id && other.layer == Some(layer)),
+        }
+    }
+
+    /// Catches presses and releases of Button outputs that need them,
+    /// acting on them if `edges` is set.
+    fn update_buttons(&mut self, edges: bool) {
+        let current = self
+            .axes
+            .iter()
+            .filter_map(|(id, combine_fn)| match combine_fn {
+                AxisCombineFn::Button {
+                    mode,
+                    behavior,
+                    inputs,
+                } if *behavior != ButtonBehavior::Follow => {
+                    Some((*id, *behavior, self.pressed(mode, inputs)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (id, behavior, pressed) in current {
+            let state = self.buttons.entry(id).or_default();
+            if !pressed {
+                // However the inputs were let go, autofire stops.
+                state.turbo_since = None;
+            }
+            if std::mem::replace(&mut state.pressed, pressed) == pressed || !edges {
+                continue;
+            }
+            match behavior {
+                ButtonBehavior::Toggle if pressed => state.on = !state.on,
+                ButtonBehavior::Pulse(length) => state.pulse_until = Some(self.now + length),
+                ButtonBehavior::OnRelease(length) if !pressed => {
+                    state.pulse_until = Some(self.now + length)
+                }
+                ButtonBehavior::Turbo { .. } if pressed => state.turbo_since = Some(self.now),
+                _ => {}
+            }
+        }
+    }
+
+    /// How long a hold and the gap in a double tap of `id` are, going
+    /// by the first inputs that count them, if any.
+    fn press_timing(&self, id: &InputAxisId) -> (Option<Duration>, Option<Duration>) {
+        let presses = self
+            .axes
+            .values()
+            .flat_map(|combine_fn| combine_fn.inputs())
+            .filter(|input| input.id == *id)
+            .filter_map(|input| input.press)
+            .collect::<Vec<_>>();
+        (
+            presses.iter().find_map(|press| match press {
+                Press::Hold(length) => Some(*length),
+                _ => None,
+            }),
+            presses.iter().find_map(|press| match press {
+                Press::DoubleTap(gap) => Some(*gap),
+                _ => None,
+            }),
+        )
+    }
+
+    /// Follows button `id` through its presses, if any input counts
+    /// them.
+    fn press(&mut self, id: InputAxisId, down: bool) {
+        let counted = self
+            .axes
+            .values()
+            .flat_map(|combine_fn| combine_fn.inputs())
+            .any(|input| input.id == id && input.press.is_some());
+        if !counted {
+            return;
+        }
+        let (_, double_tap) = self.press_timing(&id);
+        let now = self.now;
+        let state = self.presses.entry(id).or_insert(PressState {
+            phase: Phase::Up,
+            tap_until: None,
+        });
+        state.phase = match (state.phase, down) {
+            (Phase::Up, true) => Phase::Down(now),
+            (Phase::Tapped(_), true) => Phase::DoubleTapped,
+            (Phase::Down(_), false) if double_tap.is_some() => Phase::Tapped(now),
+            (Phase::Down(_), false) => {
+                state.tap_until = Some(now + TAP_LENGTH);
+                Phase::Up
+            }
+            (Phase::Held | Phase::DoubleTapped, false) => Phase::Up,
+            (phase, _) => phase,
+        };
+    }
+
+    /// Moves buttons on to being held or tapped once they've been down
+    /// or up long enough, and ends taps. Returns whether anything
+    /// changed.
+    fn advance_presses(&mut self) -> bool {
+        let now = self.now;
+        let timings = self
+            .presses
+            .keys()
+            .map(|id| (*id, self.press_timing(id)))
+            .collect::<HashMap<_, _>>();
+        let mut changed = false;
+        for (id, state) in self.presses.iter_mut() {
+            let (hold, double_tap) = timings[id];
+            match (state.phase, hold, double_tap) {
+                (Phase::Down(since), Some(hold), _) if since + hold <= now => {
+                    state.phase = Phase::Held;
+                    changed = true;
+                }
+                (Phase::Tapped(at), _, Some(gap)) if at + gap <= now => {
+                    state.phase = Phase::Up;
+                    state.tap_until = Some(now + TAP_LENGTH);
+                    changed = true;
+                }
+                _ => {}
+            }
+            if matches!(state.tap_until, Some(until) if until <= now) {
+                state.tap_until = None;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Whether any pulse ends after `since` and by `self.now`.
+    fn pulse_ended_since(&self, since: Duration) -> bool {
+        self.buttons.values().any(|state| match state.pulse_until {
+            Some(until) => since < until && until <= self.now,
+            None => false,
+        })
+    }
+
+    fn autofiring(&self) -> bool {
+        self.buttons
+            .values()
+            .any(|state| state.turbo_since.is_some())
+    }
+
+    pub fn reset_joystick(&mut self, joystick: JoystickId) {
+        self.axis_states.retain(|id, _| id.joystick != joystick);
+        self.positions.retain(|id, _| id.joystick != joystick);
+        self.engaged.retain(|id, _| id.joystick != joystick);
+        self.presses.retain(|id, _| id.joystick != joystick);
+        let layers = &self.layers;
+        self.active_layers.retain(|index| {
+            let layer = &layers[index.0];
+            layer.mode == LayerMode::Latching || layer.shift.joystick != joystick
+        });
+        self.update_chords();
+        // Losing a device isn't letting go of its buttons.
+        self.update_buttons(false);
+    }
+
+    /// Starts suppressing the inputs of chords that have just been
+    /// completed, and stops once they've all been let go.
+    fn update_chords(&mut self) {
+        let changes = self
+            .axes
+            .iter()
+            .filter_map(|(id, combine_fn)| match combine_fn {
+                AxisCombineFn::Chord {
+                    suppress: true,
+                    inputs,
+                } => {
+                    let held = inputs.iter().filter(|input| self.chord_member_down(input));
+                    match held.count() {
+                        0 => Some((*id, false)),
+                        count if count == inputs.len() => Some((*id, true)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (id, suppressing) in changes {
+            if suppressing {
+                self.chords.insert(id);
+            } else {
+                self.chords.remove(&id);
+            }
+        }
+    }
+
+    fn chord_member_down(&self, input: &InputAxis) -> bool {
+        matches!(self.own_value(input), Some(value) if value != 0)
+    }
+
+    /// Whether a chord is keeping `id` to itself.
+    fn suppressed(&self, id: &InputAxisId) -> bool {
+        self.chords.iter().any(|chord| {
+            self.axes.get(chord).map_or(false, |combine_fn| {
+                combine_fn.inputs().iter().any(|input| input.id == *id)
+            })
+        })
+    }
+
+    /// Whether any input times out after `since` and by `self.now`.
+    fn timed_out_since(&self, since: Duration) -> bool {
+        self.axes
+            .values()
+            .flat_map(|combine_fn| combine_fn.inputs())
+            .any(
+                |input| match (input.rel_mode, self.touched.get(&input.id)) {
+                    (RelMode::Timeout(timeout), Some(touched)) => {
+                        let deadline = *touched + timeout;
+                        since < deadline && deadline <= self.now
+                    }
+                    _ => false,
+                },
+            )
+    }
+
+    /// Whether anything changes with time alone, so that the mux needs
+    /// [`MuxInput::Tick`]s. Without any, it only acts on events.
+    pub fn needs_ticks(&self) -> bool {
+        self.rate != ReportRate::default()
+            || self.axes.values().any(|combine_fn| {
+                let timed_behavior = matches!(
+                    combine_fn,
+                    AxisCombineFn::Button {
+                        behavior: ButtonBehavior::Pulse(_)
+                            | ButtonBehavior::OnRelease(_)
+                            | ButtonBehavior::Turbo { .. },
+                        ..
+                    }
                 );
+                timed_behavior
+                    || combine_fn.inputs().iter().any(|input| {
+                        input.press.is_some() || matches!(input.rel_mode, RelMode::Timeout(_))
+                    })
+            })
+    }
+
+    pub fn handle(&mut self, input: MuxInput) {
+        match input {
+            MuxInput::Update(update) => self.update(update),
+            MuxInput::Seed(update) => self.apply(update, false),
+            MuxInput::Disconnected(joystick) => {
+                self.reset_joystick(joystick);
+                self.send_output();
+            }
+            MuxInput::Tick(now) => {
+                let since = self.now;
+                self.now = since.max(now);
+                let pressed = self.advance_presses();
+                let keepalive_due = match (self.rate.keepalive, self.sent_at) {
+                    (Some(keepalive), Some(sent_at)) => sent_at + keepalive <= self.now,
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                };
+                if keepalive_due {
+                    self.send(self.output());
+                } else if self.held
+                    || pressed
+                    || self.timed_out_since(since)
+                    || self.pulse_ended_since(since)
+                    || self.autofiring()
+                {
+                    self.send_output();
+                }
+            }
+        }
+    }
+
+    /// `input`'s bounds, widened to what it's actually done if
+    /// auto-ranging is on. Inverted inputs have them backwards.
+    fn bounds(&self, input: &InputAxis) -> (i64, i64) {
+        match self.seen.get(&input.id) {
+            Some(&(low, high)) if input.lower_bound <= input.upper_bound => {
+                (input.lower_bound.min(low), input.upper_bound.max(high))
             }
+            Some(&(low, high)) => (input.lower_bound.max(high), input.upper_bound.min(low)),
+            None => (input.lower_bound, input.upper_bound),
         }
     }
 
+    /// Scales `value` from `input`'s bounds to the output range.
+    fn scaled(&self, input: &InputAxis, value: i64) -> i64 {
+        let (lower, upper) = self.bounds(input);
+        match input.center {
+            None => {
+                OUTPUT_LOWER_BOUND
+                    + ((value - lower) * (OUTPUT_UPPER_BOUND - OUTPUT_LOWER_BOUND)
+                        / (upper - lower))
+            }
+            Some(center)
+                if upper != center && (value - center).signum() == (upper - center).signum() =>
+            {
+                (value - center) * OUTPUT_UPPER_BOUND / (upper - center)
+            }
+            Some(center) if lower == center => 0,
+            Some(center) => (value - center) * OUTPUT_LOWER_BOUND / (lower - center),
+        }
+    }
+
+    /// The current value of `input` in its own units, going by its
+    /// [`RelMode`], or `None` if it hasn't sent anything or doesn't count in
+    /// the active layer or a chord is suppressing it.
+    fn value(&self, input: &InputAxis) -> Option<i64> {
+        if self.suppressed(&input.id) {
+            return None;
+        }
+        self.own_value(input)
+    }
+
+    /// [`Self::value`], whether or not a chord is suppressing it.
+    fn own_value(&self, input: &InputAxis) -> Option<i64> {
+        if !self.in_layer(input) {
+            return None;
+        }
+        if let Some(press) = input.press {
+            let state = self.presses.get(&input.id)?;
+            let pressed = match press {
+                Press::Tap => matches!(state.tap_until, Some(until) if self.now < until),
+                Press::Hold(_) => state.phase == Phase::Held,
+                Press::DoubleTap(_) => state.phase == Phase::DoubleTapped,
+            };
+            return Some(pressed.into());
+        }
+        let event = self.axis_states.get(&input.id)?;
+        match input.rel_mode {
+            RelMode::Hold => Some(event.value.into()),
+            RelMode::Timeout(timeout) => match self.touched.get(&input.id) {
+                Some(touched) if self.now < *touched + timeout => Some(event.value.into()),
+                _ => Some(0),
+            },
+            RelMode::Integrate(_) => self
+                .positions
+                .get(&input.id)
+                .map(|position| position.round() as i64),
+        }
+    }
+
+    /// The current value of `input`, shaped by its response and scaled
+    /// to the output range.
+    fn normalized(&self, input: &InputAxis) -> i64 {
+        let value = self.unshaped(input);
+        if input.response == Response::default() {
+            return value;
+        }
+        let shaped = input
+            .response
+            .apply(value as f64 / OUTPUT_UPPER_BOUND as f64);
+        (shaped * OUTPUT_UPPER_BOUND as f64).round() as i64
+    }
+
+    /// Like [`Self::normalized`], but before the input's response is
+    /// applied.
+    fn unshaped(&self, input: &InputAxis) -> i64 {
+        match self.value(input) {
+            Some(value) => self.scaled(input, value),
+            None => 0,
+        }
+    }
+
+    fn last_moved(&self, input: &InputAxis) -> Option<(i64, i64)> {
+        self.axis_states
+            .get(&input.id)
+            .map(|event| (event.time.tv_sec, event.time.tv_usec))
+    }
+
+    /// Whether any of a Button output's inputs are pressed.
+    fn pressed(&self, mode: &ButtonMode, inputs: &[InputAxis]) -> bool {
+        inputs.iter().any(|input| match self.value(input) {
+            Some(value) => match mode {
+                ButtonMode::NonZero => value != 0,
+                ButtonMode::Positive => value > 0,
+                ButtonMode::Negative => value < 0,
+            },
+            None => false,
+        })
+    }
+
     pub fn output_axis(&self, axis_id: &OutputAxisId) -> Option<i64> {
         match self.axes.get(axis_id) {
             Some(combine_fn) => match combine_fn {
-                AxisCombineFn::Button { inputs, mode } => {
-                    let pressed = inputs
-                        .iter()
-                        .map(|input| match self.axis_states.get(&input.id) {
-                            Some(event) => match mode {
-                                ButtonMode::NonZero => event.value != 0,
-                                ButtonMode::Positive => event.value > 0,
-                                ButtonMode::Negative => event.value < 0,
-                            },
-                            None => false,
-                        })
-                        .any(|value| value);
+                AxisCombineFn::Button {
+                    inputs,
+                    mode,
+                    behavior,
+                } => {
+                    let state = self.buttons.get(axis_id);
+                    let pressed = match behavior {
+                        ButtonBehavior::Follow => self.pressed(mode, inputs),
+                        ButtonBehavior::Toggle => state.map_or(false, |state| state.on),
+                        ButtonBehavior::Pulse(_) | ButtonBehavior::OnRelease(_) => state
+                            .and_then(|state| state.pulse_until)
+                            .map_or(false, |until| self.now < until),
+                        ButtonBehavior::Turbo { on, off } => state
+                            .and_then(|state| state.turbo_since)
+                            .map_or(false, |since| {
+                                let period = (*on + *off).as_nanos().max(1);
+                                let elapsed = self.now.saturating_sub(since);
+                                elapsed.as_nanos() % period < on.as_nanos()
+                            }),
+                    };
                     if pressed {
                         Some(1)
                     } else {
@@ -168,16 +1030,35 @@ impl JoystickMux {
                 }
                 AxisCombineFn::LargestMagnitude { inputs } => inputs
                     .iter()
-                    .map(|input| match self.axis_states.get(&input.id) {
-                        Some(event) => {
-                            OUTPUT_LOWER_BOUND
-                                + ((i64::from(event.value) - input.lower_bound)
-                                    * (OUTPUT_UPPER_BOUND - OUTPUT_LOWER_BOUND)
-                                    / (input.upper_bound - input.lower_bound))
-                        }
-                        None => 0,
-                    })
+                    .map(|input| self.normalized(input))
                     .max_by_key(|value| value.abs()),
+                AxisCombineFn::Sum { inputs } => {
+                    let sum: f64 = inputs
+                        .iter()
+                        .map(|(input, weight)| self.normalized(input) as f64 * weight)
+                        .sum();
+                    Some((sum.round() as i64).clamp(OUTPUT_LOWER_BOUND, OUTPUT_UPPER_BOUND))
+                }
+                AxisCombineFn::Priority { mode, inputs } => {
+                    let mut active = inputs
+                        .iter()
+                        .filter(|(input, threshold)| self.unshaped(input).abs() > *threshold)
+                        .map(|(input, _)| input);
+                    let chosen = match mode {
+                        PriorityMode::Ranked => active.next(),
+                        PriorityMode::LastMoved => {
+                            active.max_by_key(|input| self.last_moved(input))
+                        }
+                    };
+                    chosen
+                        .or_else(|| inputs.first().map(|(input, _)| input))
+                        .map(|input| self.normalized(input))
+                }
+                AxisCombineFn::Chord { inputs, .. } => {
+                    let down = !inputs.is_empty()
+                        && inputs.iter().all(|input| self.chord_member_down(input));
+                    Some(down.into())
+                }
             },
             None => None,
         }
@@ -191,9 +1072,32 @@ impl JoystickMux {
         )
     }
 
+    /// Sends the current state if it's changed since the last report
+    /// and the report rate allows.
     pub fn send_output(&mut self) {
+        self.report(self.output());
+    }
+
+    fn report(&mut self, output: OutputState) {
+        if output.axes == self.sent {
+            self.held = false;
+            return;
+        }
+        if let (Some(min_interval), Some(sent_at)) = (self.rate.min_interval, self.sent_at) {
+            if self.now < sent_at + min_interval {
+                self.held = true;
+                return;
+            }
+        }
+        self.send(output);
+    }
+
+    fn send(&mut self, output: OutputState) {
+        self.sent = output.axes.clone();
+        self.sent_at = Some(self.now);
+        self.held = false;
         if let Some(sender) = &self.output_s {
-            sender.send(self.output()).expect("Failed to send state");
+            sender.send(output).expect("Failed to send state");
         }
     }
 }
@@ -201,24 +1105,85 @@ impl JoystickMux {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use evdev_rs::enums::EV_ABS;
+    use evdev_rs::enums::{EV_ABS, EV_KEY, EV_REL};
 
     const ZERO_TIME: evdev_rs::TimeVal = evdev_rs::TimeVal {
         tv_sec: 0,
         tv_usec: 0,
     };
 
+    fn id(joystick: u16, axis: EventCode) -> InputAxisId {
+        InputAxisId {
+            joystick: JoystickId(joystick),
+            axis,
+        }
+    }
+
+    fn output(axis: EventCode) -> OutputAxisId {
+        OutputAxisId { device: 0, axis }
+    }
+
+    /// A mux driving `outputs` on the first emulated joystick, and
+    /// where it sends its reports.
+    fn mux(
+        outputs: Vec<(EventCode, AxisCombineFn)>,
+    ) -> (JoystickMux, crossbeam_channel::Receiver<OutputState>) {
+        let (output_s, output_r) = crossbeam_channel::unbounded();
+        let mut m = JoystickMux::new(Some(output_s));
+        for (axis, combine_fn) in outputs {
+            m.configure_axis(output(axis), combine_fn);
+        }
+        (m, output_r)
+    }
+
+    /// Sends `value` from `axis` on `joystick`, stamped `ms`
+    /// milliseconds in.
+    fn event(m: &mut JoystickMux, joystick: u16, axis: EventCode, ms: i64, value: i32) {
+        m.update(AxisUpdate {
+            joystick: JoystickId(joystick),
+            event: InputEvent {
+                time: evdev_rs::TimeVal {
+                    tv_sec: ms / 1000,
+                    tv_usec: ms % 1000 * 1000,
+                },
+                event_code: axis,
+                value,
+            },
+        });
+    }
+
+    /// Tells the mux it's now `ms` milliseconds in, on the same clock as
+    /// [`event`].
+    fn tick(m: &mut JoystickMux, ms: u64) {
+        m.handle(MuxInput::Tick(Duration::from_millis(ms)));
+    }
+
+    /// The value of every output, in order.
+    fn values(m: &JoystickMux) -> Vec<i64> {
+        m.output().axes.iter().map(|(_, value)| *value).collect()
+    }
+
     #[test]
     fn test_inputless_axis() {
         let mut m = JoystickMux::new(None);
         m.configure_axis(
-            OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
+            OutputAxisId {
+                device: 0,
+                axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+            },
             AxisCombineFn::LargestMagnitude { inputs: vec![] },
         );
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 0)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    0
+                )],
             }
         );
     }
@@ -227,22 +1192,32 @@ mod tests {
     fn test_axis_with_no_data() {
         let mut m = JoystickMux::new(None);
         m.configure_axis(
-            OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
+            OutputAxisId {
+                device: 0,
+                axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+            },
             AxisCombineFn::LargestMagnitude {
-                inputs: vec![InputAxis {
-                    id: InputAxisId {
+                inputs: vec![InputAxis::new(
+                    InputAxisId {
                         joystick: JoystickId(0),
                         axis: EventCode::EV_ABS(EV_ABS::ABS_X),
                     },
-                    lower_bound: -32767,
-                    upper_bound: 32767,
-                }],
+                    -32767,
+                    32767,
+                )],
             },
         );
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 0)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    0
+                )],
             }
         );
     }
@@ -251,16 +1226,19 @@ mod tests {
     fn test_axis_with_some_data() {
         let mut m = JoystickMux::new(None);
         m.configure_axis(
-            OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
+            OutputAxisId {
+                device: 0,
+                axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+            },
             AxisCombineFn::LargestMagnitude {
-                inputs: vec![InputAxis {
-                    id: InputAxisId {
+                inputs: vec![InputAxis::new(
+                    InputAxisId {
                         joystick: JoystickId(0),
                         axis: EventCode::EV_ABS(EV_ABS::ABS_X),
                     },
-                    lower_bound: -32767,
-                    upper_bound: 32767,
-                }],
+                    -32767,
+                    32767,
+                )],
             },
         );
         m.update(AxisUpdate {
@@ -274,7 +1252,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 5)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    5
+                )],
             }
         );
     }
@@ -283,25 +1268,28 @@ mod tests {
     fn test_largest_magnitude() {
         let mut m = JoystickMux::new(None);
         m.configure_axis(
-            OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
+            OutputAxisId {
+                device: 0,
+                axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+            },
             AxisCombineFn::LargestMagnitude {
                 inputs: vec![
-                    InputAxis {
-                        id: InputAxisId {
+                    InputAxis::new(
+                        InputAxisId {
                             joystick: JoystickId(0),
                             axis: EventCode::EV_ABS(EV_ABS::ABS_X),
                         },
-                        lower_bound: -32767,
-                        upper_bound: 32767,
-                    },
-                    InputAxis {
-                        id: InputAxisId {
+                        -32767,
+                        32767,
+                    ),
+                    InputAxis::new(
+                        InputAxisId {
                             joystick: JoystickId(0),
                             axis: EventCode::EV_ABS(EV_ABS::ABS_Y),
                         },
-                        lower_bound: -32767,
-                        upper_bound: 32767,
-                    },
+                        -32767,
+                        32767,
+                    ),
                 ],
             },
         );
@@ -325,34 +1313,289 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 12)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    12
+                )],
             }
         );
     }
 
+    #[test]
+    fn test_sum_weights_and_clamps() {
+        let (x, y) = (
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_ABS(EV_ABS::ABS_Y),
+        );
+        let axis = |code| InputAxis::new(id(0, code), -32767, 32767);
+        let (mut m, _) = mux(vec![(
+            x,
+            AxisCombineFn::Sum {
+                inputs: vec![(axis(x), 1.0), (axis(y), 0.25)],
+            },
+        )]);
+        event(&mut m, 0, x, 0, 1000);
+        event(&mut m, 0, y, 0, -400);
+        assert_eq!(m.output_axis(&output(x)), Some(900));
+
+        event(&mut m, 0, x, 0, 32000);
+        event(&mut m, 0, y, 0, 32000);
+        assert_eq!(m.output_axis(&output(x)), Some(OUTPUT_UPPER_BOUND));
+    }
+
+    #[test]
+    fn test_priority_ranked() {
+        let x = EventCode::EV_ABS(EV_ABS::ABS_X);
+        let stick = |joystick| (InputAxis::new(id(joystick, x), -32767, 32767), 1000);
+        let (mut m, _) = mux(vec![(
+            x,
+            AxisCombineFn::Priority {
+                mode: PriorityMode::Ranked,
+                inputs: vec![stick(0), stick(1)],
+            },
+        )]);
+        assert_eq!(m.output_axis(&output(x)), Some(0));
+
+        // The co-pilot's stick resting off center doesn't count.
+        event(&mut m, 0, x, 1000, 200);
+        event(&mut m, 1, x, 2000, -500);
+        assert_eq!(m.output_axis(&output(x)), Some(200));
+
+        event(&mut m, 1, x, 3000, -5000);
+        assert_eq!(m.output_axis(&output(x)), Some(-5000));
+
+        event(&mut m, 0, x, 4000, 3000);
+        assert_eq!(m.output_axis(&output(x)), Some(3000));
+    }
+
+    #[test]
+    fn test_priority_last_moved() {
+        let x = EventCode::EV_ABS(EV_ABS::ABS_X);
+        let stick = |joystick| (InputAxis::new(id(joystick, x), -32767, 32767), 1000);
+        let (mut m, _) = mux(vec![(
+            x,
+            AxisCombineFn::Priority {
+                mode: PriorityMode::LastMoved,
+                inputs: vec![stick(0), stick(1)],
+            },
+        )]);
+        event(&mut m, 1, x, 1000, -5000);
+        event(&mut m, 0, x, 2000, 3000);
+        assert_eq!(m.output_axis(&output(x)), Some(3000));
+
+        event(&mut m, 1, x, 3000, -6000);
+        assert_eq!(m.output_axis(&output(x)), Some(-6000));
+
+        // Jitter inside the threshold isn't a move.
+        event(&mut m, 0, x, 4000, 500);
+        assert_eq!(m.output_axis(&output(x)), Some(-6000));
+    }
+
+    #[test]
+    fn test_rel_timeout() {
+        let (x, rel_x) = (
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_REL(EV_REL::REL_X),
+        );
+        let input = InputAxis {
+            rel_mode: RelMode::Timeout(Duration::from_secs(1)),
+            ..InputAxis::new(id(0, rel_x), -100, 100)
+        };
+        let (mut m, output_r) = mux(vec![(
+            x,
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![input],
+            },
+        )]);
+        event(&mut m, 0, rel_x, 0, 50);
+        tick(&mut m, 0);
+        assert_eq!(m.output_axis(&output(x)), Some(16383));
+        assert!(output_r.try_recv().is_err());
+
+        tick(&mut m, 2000);
+        assert_eq!(m.output_axis(&output(x)), Some(0));
+        assert_eq!(output_r.try_recv().map(|state| state.axes[0].1), Ok(0));
+
+        // It only goes back to zero once.
+        tick(&mut m, 3000);
+        assert!(output_r.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_only_syn_report_sends() {
+        let (x, rel_x) = (
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_REL(EV_REL::REL_X),
+        );
+        let input = InputAxis::new(id(0, rel_x), -100, 100);
+        let (mut m, output_r) = mux(vec![(
+            x,
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![input],
+            },
+        )]);
+        event(&mut m, 0, rel_x, 0, 50);
+        event(&mut m, 0, EventCode::EV_SYN(EV_SYN::SYN_DROPPED), 0, 0);
+        assert!(output_r.try_recv().is_err());
+        event(&mut m, 0, EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0, 0);
+        assert_eq!(output_r.try_recv().map(|state| state.axes[0].1), Ok(16383));
+    }
+
+    #[test]
+    fn test_report_rate() {
+        let (x, rel_x) = (
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_REL(EV_REL::REL_X),
+        );
+        let input = InputAxis::new(id(0, rel_x), -100, 100);
+        let (mut m, output_r) = mux(vec![(
+            x,
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![input],
+            },
+        )]);
+        m.set_report_rate(ReportRate {
+            keepalive: Some(Duration::from_secs(1)),
+            min_interval: Some(Duration::from_millis(100)),
+        });
+        let report = |m: &mut JoystickMux| event(m, 0, EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0, 0);
+        let sent = || {
+            output_r
+                .try_iter()
+                .map(|state| state.axes[0].1)
+                .collect::<Vec<_>>()
+        };
+
+        event(&mut m, 0, rel_x, 0, 50);
+        report(&mut m);
+        // Nothing changed.
+        report(&mut m);
+        assert_eq!(sent(), vec![16383]);
+
+        // Too soon, so it waits for the next tick.
+        event(&mut m, 0, rel_x, 0, 100);
+        report(&mut m);
+        assert_eq!(sent(), vec![]);
+        tick(&mut m, 200);
+        assert_eq!(sent(), vec![32767]);
+
+        tick(&mut m, 500);
+        assert_eq!(sent(), vec![]);
+        tick(&mut m, 2000);
+        assert_eq!(sent(), vec![32767]);
+    }
+
+    #[test]
+    fn test_rel_integrate() {
+        let (x, rel_x) = (
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_REL(EV_REL::REL_X),
+        );
+        let input = InputAxis {
+            rel_mode: RelMode::Integrate(0.5),
+            ..InputAxis::new(id(0, rel_x), -100, 100)
+        };
+        let (mut m, _) = mux(vec![(
+            x,
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![input],
+            },
+        )]);
+        assert_eq!(m.output_axis(&output(x)), Some(0));
+        event(&mut m, 0, rel_x, 0, 100);
+        assert_eq!(m.output_axis(&output(x)), Some(16383));
+        event(&mut m, 0, rel_x, 0, 1000);
+        assert_eq!(m.output_axis(&output(x)), Some(32767));
+        event(&mut m, 0, rel_x, 0, -40);
+        assert_eq!(m.output_axis(&output(x)), Some(26213));
+    }
+
+    #[test]
+    fn test_center_and_auto_range() {
+        let x = EventCode::EV_ABS(EV_ABS::ABS_X);
+        let input = InputAxis {
+            center: Some(400),
+            ..InputAxis::new(id(0, x), 0, 1000)
+        };
+        let (mut m, _) = mux(vec![(
+            x,
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![input.clone()],
+            },
+        )]);
+        let at = |m: &mut JoystickMux, value| {
+            event(m, 0, x, 0, value);
+            m.output_axis(&output(x))
+        };
+        assert_eq!(at(&mut m, 400), Some(0));
+        assert_eq!(at(&mut m, 200), Some(-16383));
+        assert_eq!(at(&mut m, 700), Some(16383));
+
+        m.configure_axis(
+            output(x),
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![-input],
+            },
+        );
+        assert_eq!(at(&mut m, 0), Some(32767));
+        assert_eq!(at(&mut m, 1000), Some(-32767));
+
+        m.set_auto_range(true);
+        assert_eq!(at(&mut m, 1600), Some(-32767));
+        assert_eq!(at(&mut m, 1000), Some(-16383));
+    }
+
+    #[test]
+    fn test_response() {
+        let response = Response {
+            deadzone: 0.1,
+            saturation: 0.9,
+            curve: Curve::Linear,
+        };
+        assert_eq!(response.apply(0.05), 0.0);
+        assert_eq!(response.apply(-0.5), -0.5);
+        assert_eq!(response.apply(0.95), 1.0);
+
+        let table = Curve::Table(vec![(0.5, 0.2), (1.0, 1.0)]);
+        assert!((table.apply(0.25) - 0.1).abs() < 1e-9);
+        assert!((table.apply(0.75) - 0.6).abs() < 1e-9);
+
+        for curve in [Curve::Exponential(3.0), Curve::S(1.0)] {
+            assert!(curve.apply(0.25) < 0.25);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-9);
+        }
+    }
+
     #[test]
     fn test_negative_magnitude() {
         let mut m = JoystickMux::new(None);
         m.configure_axis(
-            OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
+            OutputAxisId {
+                device: 0,
+                axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+            },
             AxisCombineFn::LargestMagnitude {
                 inputs: vec![
-                    InputAxis {
-                        id: InputAxisId {
+                    InputAxis::new(
+                        InputAxisId {
                             joystick: JoystickId(0),
                             axis: EventCode::EV_ABS(EV_ABS::ABS_X),
                         },
-                        lower_bound: -32767,
-                        upper_bound: 32767,
-                    },
-                    InputAxis {
-                        id: InputAxisId {
+                        -32767,
+                        32767,
+                    ),
+                    InputAxis::new(
+                        InputAxisId {
                             joystick: JoystickId(0),
                             axis: EventCode::EV_ABS(EV_ABS::ABS_Y),
                         },
-                        lower_bound: -32767,
-                        upper_bound: 32767,
-                    },
+                        -32767,
+                        32767,
+                    ),
                 ],
             },
         );
@@ -375,7 +1618,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), -12)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    -12
+                )],
             }
         );
     }
@@ -384,16 +1634,19 @@ mod tests {
     fn test_input_range() {
         let mut m = JoystickMux::new(None);
         m.configure_axis(
-            OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
+            OutputAxisId {
+                device: 0,
+                axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+            },
             AxisCombineFn::LargestMagnitude {
-                inputs: vec![InputAxis {
-                    id: InputAxisId {
+                inputs: vec![InputAxis::new(
+                    InputAxisId {
                         joystick: JoystickId(0),
                         axis: EventCode::EV_ABS(EV_ABS::ABS_X),
                     },
-                    lower_bound: -5,
-                    upper_bound: 5,
-                }],
+                    -5,
+                    5,
+                )],
             },
         );
         m.update(AxisUpdate {
@@ -407,7 +1660,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 0)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    0
+                )],
             }
         );
         m.update(AxisUpdate {
@@ -421,7 +1681,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 32767)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    32767
+                )],
             }
         );
         m.update(AxisUpdate {
@@ -435,7 +1702,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), -32767)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    -32767
+                )],
             }
         );
         m.update(AxisUpdate {
@@ -449,7 +1723,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 6553)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    6553
+                )],
             }
         );
         m.update(AxisUpdate {
@@ -463,7 +1744,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), -6554)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    -6554
+                )],
             }
         );
     }
@@ -472,16 +1760,19 @@ mod tests {
     fn test_inverted_input_range() {
         let mut m = JoystickMux::new(None);
         m.configure_axis(
-            OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)),
+            OutputAxisId {
+                device: 0,
+                axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+            },
             AxisCombineFn::LargestMagnitude {
-                inputs: vec![InputAxis {
-                    id: InputAxisId {
+                inputs: vec![InputAxis::new(
+                    InputAxisId {
                         joystick: JoystickId(0),
                         axis: EventCode::EV_ABS(EV_ABS::ABS_X),
                     },
-                    lower_bound: 5,
-                    upper_bound: -5,
-                }],
+                    5,
+                    -5,
+                )],
             },
         );
         m.update(AxisUpdate {
@@ -495,7 +1786,14 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), -32767)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    -32767
+                )],
             }
         );
         m.update(AxisUpdate {
@@ -509,8 +1807,505 @@ mod tests {
         assert_eq!(
             m.output(),
             OutputState {
-                axes: vec![(OutputAxisId(EventCode::EV_ABS(EV_ABS::ABS_X)), 32767)],
+                time: None,
+                axes: vec![(
+                    OutputAxisId {
+                        device: 0,
+                        axis: EventCode::EV_ABS(EV_ABS::ABS_X),
+                    },
+                    32767
+                )],
             }
         );
     }
+
+    #[test]
+    fn test_swap_axes_keeps_state() {
+        let (x, y) = (
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_ABS(EV_ABS::ABS_Y),
+        );
+        let input = InputAxis::new(id(0, x), -32767, 32767);
+        let (mut m, _) = mux(vec![(
+            x,
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![input.clone()],
+            },
+        )]);
+        event(&mut m, 0, x, 0, 5);
+        let old = m.swap_axes(
+            [(
+                output(y),
+                AxisCombineFn::LargestMagnitude {
+                    inputs: vec![-input],
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(old.len(), 1);
+        assert_eq!(
+            m.output(),
+            OutputState {
+                time: None,
+                axes: vec![(output(y), -5)],
+            }
+        );
+    }
+
+    #[test]
+    fn test_swap_axes_resets_changed_buttons() {
+        let (b0, b1, trigger) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_1),
+            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        );
+        let toggle = |button| {
+            [(
+                output(trigger),
+                AxisCombineFn::Button {
+                    mode: ButtonMode::NonZero,
+                    behavior: ButtonBehavior::Toggle,
+                    inputs: vec![InputAxis::new(id(0, button), 0, 1)],
+                },
+            )]
+            .into_iter()
+            .collect::<HashMap<_, _>>()
+        };
+        let mut m = JoystickMux::new(None);
+        m.swap_axes(toggle(b0));
+        event(&mut m, 0, b0, 1000, 1);
+        event(&mut m, 0, b0, 1000, 0);
+        assert_eq!(values(&m), vec![1]);
+
+        // Reloading the same config leaves the toggle on.
+        m.swap_axes(toggle(b0));
+        assert_eq!(values(&m), vec![1]);
+
+        m.swap_axes(toggle(b1));
+        assert_eq!(values(&m), vec![0]);
+        event(&mut m, 0, b1, 1000, 1);
+        assert_eq!(values(&m), vec![1]);
+
+        // A button held through a reload wasn't just pressed.
+        event(&mut m, 0, b0, 1000, 1);
+        m.swap_axes(toggle(b0));
+        assert_eq!(values(&m), vec![0]);
+        event(&mut m, 0, b0, 1000, 0);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(values(&m), vec![1]);
+    }
+
+    #[test]
+    fn test_disconnect_resets_joystick() {
+        let x = EventCode::EV_ABS(EV_ABS::ABS_X);
+        let (mut m, _output_r) = mux(vec![(
+            x,
+            AxisCombineFn::LargestMagnitude {
+                inputs: vec![
+                    InputAxis::new(id(0, x), -32767, 32767),
+                    InputAxis::new(id(1, x), -32767, 32767),
+                ],
+            },
+        )]);
+        event(&mut m, 0, x, 0, 12);
+        event(&mut m, 1, x, 0, 5);
+        m.handle(MuxInput::Disconnected(JoystickId(0)));
+        assert_eq!(values(&m), vec![5]);
+    }
+
+    #[test]
+    fn test_momentary_layer() {
+        let (b0, b1, shift) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_1),
+            EventCode::EV_KEY(EV_KEY::BTN_280),
+        );
+        let button = |code| InputAxis::new(id(0, code), 0, 1);
+        let follow = |input| AxisCombineFn::Button {
+            mode: ButtonMode::NonZero,
+            behavior: ButtonBehavior::Follow,
+            inputs: vec![input],
+        };
+        let (mut m, _) = mux(vec![
+            (EventCode::EV_KEY(EV_KEY::BTN_TRIGGER), follow(button(b0))),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_THUMB),
+                follow(InputAxis {
+                    layer: Some(LayerId(0)),
+                    ..button(b0)
+                }),
+            ),
+            (EventCode::EV_KEY(EV_KEY::BTN_THUMB2), follow(button(b1))),
+        ]);
+        m.set_layers(vec![Layer {
+            shift: id(0, shift),
+            mode: LayerMode::Momentary,
+        }]);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(values(&m), vec![1, 0, 0]);
+        event(&mut m, 0, b0, 1000, 0);
+
+        event(&mut m, 0, shift, 1000, 1);
+        event(&mut m, 0, b0, 1000, 1);
+        // The layer doesn't use BTN_1, so it still does what it does
+        // without the layer.
+        event(&mut m, 0, b1, 1000, 1);
+        assert_eq!(values(&m), vec![0, 1, 1]);
+
+        // Pressed in the layer, so it's released in the layer too.
+        event(&mut m, 0, shift, 1000, 0);
+        assert_eq!(values(&m), vec![0, 1, 1]);
+        event(&mut m, 0, b0, 1000, 0);
+        assert_eq!(values(&m), vec![0, 0, 1]);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(values(&m), vec![1, 0, 1]);
+    }
+
+    #[test]
+    fn test_latching_layer() {
+        let (b0, b1, shift) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_1),
+            EventCode::EV_KEY(EV_KEY::BTN_280),
+        );
+        let button = |code| InputAxis::new(id(0, code), 0, 1);
+        let follow = |input| AxisCombineFn::Button {
+            mode: ButtonMode::NonZero,
+            behavior: ButtonBehavior::Follow,
+            inputs: vec![input],
+        };
+        let (mut m, _) = mux(vec![
+            (EventCode::EV_KEY(EV_KEY::BTN_TRIGGER), follow(button(b0))),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_THUMB),
+                follow(InputAxis {
+                    layer: Some(LayerId(0)),
+                    ..button(b0)
+                }),
+            ),
+            (EventCode::EV_KEY(EV_KEY::BTN_THUMB2), follow(button(b1))),
+        ]);
+        m.set_layers(vec![Layer {
+            shift: id(0, shift),
+            mode: LayerMode::Latching,
+        }]);
+        event(&mut m, 0, shift, 1000, 1);
+        event(&mut m, 0, shift, 1000, 2);
+        event(&mut m, 0, shift, 1000, 0);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(values(&m), vec![0, 1, 0]);
+
+        event(&mut m, 0, shift, 1000, 1);
+        assert_eq!(values(&m), vec![0, 1, 0]);
+        event(&mut m, 0, b0, 1000, 0);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(values(&m), vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn test_seed_isnt_a_press() {
+        let (b0, b1, shift) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_1),
+            EventCode::EV_KEY(EV_KEY::BTN_280),
+        );
+        let follow = |input| AxisCombineFn::Button {
+            mode: ButtonMode::NonZero,
+            behavior: ButtonBehavior::Follow,
+            inputs: vec![input],
+        };
+        let (mut m, _output_r) = mux(vec![
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+                follow(InputAxis::new(id(0, b0), 0, 1)),
+            ),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_THUMB),
+                follow(InputAxis {
+                    layer: Some(LayerId(0)),
+                    ..InputAxis::new(id(0, b0), 0, 1)
+                }),
+            ),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_THUMB2),
+                follow(InputAxis {
+                    press: Some(Press::Hold(Duration::from_millis(500))),
+                    ..InputAxis::new(id(0, b1), 0, 1)
+                }),
+            ),
+        ]);
+        m.set_layers(vec![Layer {
+            shift: id(0, shift),
+            mode: LayerMode::Latching,
+        }]);
+        event(&mut m, 0, b1, 1000, 0);
+        for code in [shift, b1] {
+            m.handle(MuxInput::Seed(AxisUpdate {
+                joystick: JoystickId(0),
+                event: InputEvent {
+                    time: ZERO_TIME,
+                    event_code: code,
+                    value: 1,
+                },
+            }));
+        }
+        tick(&mut m, 2000);
+        assert_eq!(values(&m), vec![0, 0, 0]);
+
+        // Neither is letting go of them.
+        event(&mut m, 0, b0, 2000, 1);
+        event(&mut m, 0, b1, 2000, 0);
+        tick(&mut m, 2010);
+        assert_eq!(values(&m), vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn test_needs_ticks() {
+        let (x, b0, trigger) = (
+            EventCode::EV_ABS(EV_ABS::ABS_X),
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        );
+        let button = |behavior, press| AxisCombineFn::Button {
+            mode: ButtonMode::NonZero,
+            behavior,
+            inputs: vec![InputAxis {
+                press,
+                ..InputAxis::new(id(0, b0), 0, 1)
+            }],
+        };
+        let (mut m, _) = mux(vec![
+            (
+                x,
+                AxisCombineFn::LargestMagnitude {
+                    inputs: vec![InputAxis::new(id(0, x), -32767, 32767)],
+                },
+            ),
+            (trigger, button(ButtonBehavior::Toggle, None)),
+        ]);
+        assert!(!m.needs_ticks());
+
+        for combine_fn in [
+            button(ButtonBehavior::Pulse(TAP_LENGTH), None),
+            button(ButtonBehavior::Follow, Some(Press::Hold(TAP_LENGTH))),
+        ] {
+            m.configure_axis(output(trigger), combine_fn);
+            assert!(m.needs_ticks());
+        }
+
+        m.configure_axis(output(trigger), button(ButtonBehavior::Follow, None));
+        assert!(!m.needs_ticks());
+        m.set_report_rate(ReportRate {
+            keepalive: Some(Duration::from_secs(1)),
+            min_interval: None,
+        });
+        assert!(m.needs_ticks());
+    }
+
+    #[test]
+    fn test_toggle() {
+        let (b0, trigger) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        );
+        let (mut m, _) = mux(vec![(
+            trigger,
+            AxisCombineFn::Button {
+                mode: ButtonMode::NonZero,
+                behavior: ButtonBehavior::Toggle,
+                inputs: vec![InputAxis::new(id(0, b0), 0, 1)],
+            },
+        )]);
+        let mut outputs = vec![];
+        for value in [1, 2, 0, 1, 0] {
+            event(&mut m, 0, b0, 1000, value);
+            outputs.push(m.output_axis(&output(trigger)));
+        }
+        assert_eq!(outputs, vec![Some(1), Some(1), Some(1), Some(0), Some(0)]);
+
+        // A button that's already down when the device is opened
+        // wasn't just pressed.
+        m.handle(MuxInput::Seed(AxisUpdate {
+            joystick: JoystickId(0),
+            event: InputEvent {
+                time: ZERO_TIME,
+                event_code: b0,
+                value: 1,
+            },
+        }));
+        assert_eq!(m.output_axis(&output(trigger)), Some(0));
+    }
+
+    #[test]
+    fn test_pulse() {
+        let (b0, trigger) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        );
+        let (mut m, output_r) = mux(vec![(
+            trigger,
+            AxisCombineFn::Button {
+                mode: ButtonMode::NonZero,
+                behavior: ButtonBehavior::Pulse(Duration::from_millis(100)),
+                inputs: vec![InputAxis::new(id(0, b0), 0, 1)],
+            },
+        )]);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(m.output_axis(&output(trigger)), Some(1));
+        tick(&mut m, 1200);
+        assert_eq!(m.output_axis(&output(trigger)), Some(0));
+        assert_eq!(output_r.try_recv().map(|state| state.axes[0].1), Ok(0));
+
+        // Letting go is another pulse.
+        event(&mut m, 0, b0, 1000, 0);
+        assert_eq!(m.output_axis(&output(trigger)), Some(1));
+    }
+
+    #[test]
+    fn test_on_release() {
+        let (b0, trigger) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        );
+        let (mut m, _output_r) = mux(vec![(
+            trigger,
+            AxisCombineFn::Button {
+                mode: ButtonMode::NonZero,
+                behavior: ButtonBehavior::OnRelease(Duration::from_millis(100)),
+                inputs: vec![InputAxis::new(id(0, b0), 0, 1)],
+            },
+        )]);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(m.output_axis(&output(trigger)), Some(0));
+        event(&mut m, 0, b0, 1000, 0);
+        assert_eq!(m.output_axis(&output(trigger)), Some(1));
+        tick(&mut m, 1200);
+        assert_eq!(m.output_axis(&output(trigger)), Some(0));
+    }
+
+    #[test]
+    fn test_turbo() {
+        let (b0, trigger) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+        );
+        let (mut m, output_r) = mux(vec![(
+            trigger,
+            AxisCombineFn::Button {
+                mode: ButtonMode::NonZero,
+                behavior: ButtonBehavior::Turbo {
+                    on: Duration::from_millis(100),
+                    off: Duration::from_millis(100),
+                },
+                inputs: vec![InputAxis::new(id(0, b0), 0, 1)],
+            },
+        )]);
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(m.output_axis(&output(trigger)), Some(1));
+        // Ticks send it without any input events.
+        tick(&mut m, 1150);
+        assert_eq!(
+            output_r.try_iter().last().map(|state| state.axes[0].1),
+            Some(0)
+        );
+        tick(&mut m, 1250);
+        assert_eq!(
+            output_r.try_iter().last().map(|state| state.axes[0].1),
+            Some(1)
+        );
+        event(&mut m, 0, b0, 1300, 0);
+        tick(&mut m, 1350);
+        assert_eq!(m.output_axis(&output(trigger)), Some(0));
+    }
+
+    #[test]
+    fn test_presses() {
+        let b0 = EventCode::EV_KEY(EV_KEY::BTN_0);
+        let on_press = |press| AxisCombineFn::Button {
+            mode: ButtonMode::NonZero,
+            behavior: ButtonBehavior::Follow,
+            inputs: vec![InputAxis {
+                press: Some(press),
+                ..InputAxis::new(id(0, b0), 0, 1)
+            }],
+        };
+        let (mut m, _output_r) = mux(vec![
+            (EventCode::EV_KEY(EV_KEY::BTN_TRIGGER), on_press(Press::Tap)),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_THUMB),
+                on_press(Press::Hold(Duration::from_millis(500))),
+            ),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_THUMB2),
+                on_press(Press::DoubleTap(Duration::from_millis(300))),
+            ),
+        ]);
+        // A tap only counts once it's too late to be a double tap.
+        event(&mut m, 0, b0, 1000, 1);
+        event(&mut m, 0, b0, 1100, 0);
+        assert_eq!(values(&m), vec![0, 0, 0]);
+        tick(&mut m, 1500);
+        assert_eq!(values(&m), vec![1, 0, 0]);
+        tick(&mut m, 2000);
+        assert_eq!(values(&m), vec![0, 0, 0]);
+
+        // Held from when the press was stamped, not when it was handled.
+        event(&mut m, 0, b0, 4000, 1);
+        tick(&mut m, 4500);
+        assert_eq!(values(&m), vec![0, 1, 0]);
+        event(&mut m, 0, b0, 4600, 0);
+        assert_eq!(values(&m), vec![0, 0, 0]);
+
+        event(&mut m, 0, b0, 6000, 1);
+        event(&mut m, 0, b0, 6100, 0);
+        event(&mut m, 0, b0, 6200, 1);
+        assert_eq!(values(&m), vec![0, 0, 1]);
+        event(&mut m, 0, b0, 6300, 0);
+        tick(&mut m, 6700);
+        assert_eq!(values(&m), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_chord() {
+        let (b0, b1) = (
+            EventCode::EV_KEY(EV_KEY::BTN_0),
+            EventCode::EV_KEY(EV_KEY::BTN_1),
+        );
+        let follow = |input| AxisCombineFn::Button {
+            mode: ButtonMode::NonZero,
+            behavior: ButtonBehavior::Follow,
+            inputs: vec![input],
+        };
+        let (first, second) = (
+            InputAxis::new(id(0, b0), 0, 1),
+            InputAxis::new(id(1, b1), 0, 1),
+        );
+        let (mut m, _) = mux(vec![
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_TRIGGER),
+                follow(first.clone()),
+            ),
+            (EventCode::EV_KEY(EV_KEY::BTN_THUMB), follow(second.clone())),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_THUMB2),
+                AxisCombineFn::Chord {
+                    suppress: true,
+                    inputs: vec![first, second],
+                },
+            ),
+        ]);
+
+        event(&mut m, 0, b0, 1000, 1);
+        assert_eq!(values(&m), vec![1, 0, 0]);
+        event(&mut m, 1, b1, 1000, 1);
+        assert_eq!(values(&m), vec![0, 0, 1]);
+        // Letting go of one member doesn't give the other back until
+        // it's been let go as well.
+        event(&mut m, 0, b0, 1000, 0);
+        assert_eq!(values(&m), vec![0, 0, 0]);
+        event(&mut m, 1, b1, 1000, 0);
+        event(&mut m, 1, b1, 1000, 1);
+        assert_eq!(values(&m), vec![0, 1, 0]);
+    }
 }
@@ -0,0 +1,90 @@
+//! Measures how long input events take to make it out to the sinks, for
+//! `run --latency`.
+
+use crate::reader;
+use evdev_rs::TimeVal;
+use std::time::{Duration, Instant};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub count: usize,
+    pub median: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Summarizes `samples`, sorting them in the process. `None` if there
+/// aren't any.
+pub fn summarize(samples: &mut [Duration]) -> Option<Summary> {
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    Some(Summary {
+        count: samples.len(),
+        median: percentile(50),
+        p99: percentile(99),
+        max: samples[samples.len() - 1],
+    })
+}
+
+/// Collects latencies and prints a summary of them every so often.
+#[derive(Debug)]
+pub struct Latency {
+    samples: Vec<Duration>,
+    since: Instant,
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Latency {
+            samples: Vec::new(),
+            since: Instant::now(),
+        }
+    }
+}
+
+impl Latency {
+    /// Records the time from `time`, which the kernel stamped the event
+    /// with, until now.
+    pub fn record(&mut self, time: TimeVal) {
+        self.samples
+            .push(reader::now().saturating_sub(reader::timestamp(&time)));
+        if self.since.elapsed() >= REPORT_INTERVAL {
+            if let Some(summary) = summarize(&mut self.samples) {
+                eprintln!(
+                    "latency over {} reports: median {:?}, p99 {:?}, max {:?}",
+                    summary.count, summary.median, summary.p99, summary.max
+                );
+            }
+            self.samples.clear();
+            self.since = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize(&mut []), None);
+        let mut samples = (1..=200)
+            .rev()
+            .map(Duration::from_micros)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summarize(&mut samples),
+            Some(Summary {
+                count: 200,
+                median: Duration::from_micros(100),
+                p99: Duration::from_micros(198),
+                max: Duration::from_micros(200),
+            })
+        );
+    }
+}
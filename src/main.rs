@@ -1,18 +1,41 @@
 use anyhow::{Context, Result};
 use clap::Parser;
+use config_loader::{ConfigInput, ConfigQuirk};
 use evdev_rs::enums::EventCode;
 use evdev_rs::DeviceWrapper;
 use std::collections::HashMap;
-use std::io::Write;
-use std::sync::Mutex;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
+mod calibration;
+mod config_loader;
 mod configuration;
+mod descriptor;
+mod device_selector;
+mod event_codes;
+mod event_loop;
 mod gadget;
+mod hotplug;
 mod joystick_mux;
+mod latency;
+mod output_sink;
+mod quirks;
+mod reader;
+mod reload;
 mod report;
 
-use joystick_mux::{AxisUpdate, InputAxis, InputAxisId, JoystickId, OutputAxisId};
+use calibration::AxisCalibration;
+use descriptor::ReportDescription;
+use joystick_mux::{
+    AxisCombineFn, InputAxis, InputAxisId, JoystickId, JoystickMux, MuxInput, OutputAxisId,
+    OutputState,
+};
+use latency::Latency;
+use output_sink::{GadgetSink, OutputSink, UinputSink};
+use reader::OpenInput;
 
 #[derive(clap::Parser)]
 struct Args {
@@ -20,32 +43,82 @@ struct Args {
     command: Command,
 }
 
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum OutputKind {
+    /// USB HID gadget devices, /dev/hidgN
+    Gadget,
+    /// Virtual joysticks on this machine
+    Uinput,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum EventLoop {
+    /// One thread waiting on every device at once
+    Epoll,
+    /// A thread per device, feeding the mux through a channel
+    Threads,
+}
+
 #[derive(clap::Subcommand)]
 enum Command {
-    Init,
+    Init {
+        /// Number of joysticks to emulate, defaults to as many as the
+        /// config uses
+        #[arg(long)]
+        devices: Option<usize>,
+        /// Config to take the report shape and number of joysticks
+        /// from, otherwise the default shape is used
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
     Uninit,
-    Run,
+    Run {
+        /// Where the emulated joysticks go
+        #[arg(long, value_enum, default_value = "gadget")]
+        output: OutputKind,
+        /// Widen each axis's range whenever it goes past it, for
+        /// devices that don't report their range accurately
+        #[arg(long)]
+        auto_range: bool,
+        /// How to wait on the input devices
+        #[arg(long, value_enum, default_value = "epoll")]
+        event_loop: EventLoop,
+        /// Print how long events take to get from the inputs to the
+        /// outputs every ten seconds
+        #[arg(long)]
+        latency: bool,
+    },
+    /// Measure the range and center of each input's axes
+    Calibrate,
+    /// Check a config file for mistakes without opening any devices
+    #[command(name = "check-config")]
+    Check {
+        /// Defaults to ~/.config/composite_joystick/config.yaml
+        config: Option<PathBuf>,
+    },
+    /// List input devices and the fields that inputs can match them on
+    #[command(name = "list-devices")]
+    ListDevices,
 }
 
-fn lower_bound_for(code: EventCode) -> i64 {
-    match code {
-        EventCode::EV_ABS(_) => -350,
-        EventCode::EV_REL(_) => -350,
-        EventCode::EV_KEY(_) => 0,
-        _ => -350,
-    }
-}
+// Relative axes don't say how far they go, so devices that aren't in
+// the quirks table get a guess. `run --auto-range` widens it.
+const DEFAULT_REL_RANGE: i64 = 350;
+
+const TICK_INTERVAL: Duration = Duration::from_millis(10);
 
-fn upper_bound_for(code: EventCode) -> i64 {
+fn default_bounds(code: EventCode) -> (i64, i64) {
     match code {
-        EventCode::EV_ABS(_) => 350,
-        EventCode::EV_REL(_) => 350,
-        EventCode::EV_KEY(_) => 1,
-        _ => 350,
+        EventCode::EV_KEY(_) => (0, 1),
+        _ => (-DEFAULT_REL_RANGE, DEFAULT_REL_RANGE),
     }
 }
 
-fn get_input_axes(device: &evdev_rs::Device, id: u16) -> HashMap<EventCode, InputAxis> {
+fn get_input_axes(
+    device: &evdev_rs::Device,
+    id: u16,
+    quirk: Option<&ConfigQuirk>,
+) -> HashMap<EventCode, InputAxis> {
     let mut result = HashMap::new();
     let iterator = evdev_rs::EventCodeIterator::new(&evdev_rs::enums::EventType::EV_ABS)
         .chain(evdev_rs::EventCodeIterator::new(
@@ -62,119 +135,350 @@ fn get_input_axes(device: &evdev_rs::Device, id: u16) -> HashMap<EventCode, Inpu
         if let Some(ai) = device.abs_info(&code) {
             result.insert(
                 code,
-                InputAxis {
-                    id,
-                    lower_bound: ai.minimum.into(),
-                    upper_bound: ai.maximum.into(),
-                },
+                InputAxis::new(id, ai.minimum.into(), ai.maximum.into()),
             );
         } else if device.has(code) {
-            result.insert(
-                code,
-                InputAxis {
-                    id,
-                    lower_bound: lower_bound_for(code),
-                    upper_bound: upper_bound_for(code),
-                },
-            );
+            let (lower_bound, upper_bound) = default_bounds(code);
+            result.insert(code, InputAxis::new(id, lower_bound, upper_bound));
         }
     }
+    if let Some(quirk) = quirk {
+        quirks::apply(quirk, &mut result);
+    }
     result
 }
 
-fn handle_device(
-    device: evdev_rs::Device,
-    id: JoystickId,
-    updates: crossbeam_channel::Sender<joystick_mux::AxisUpdate>,
-) -> ! {
+fn handle_device(mut input: OpenInput, updates: crossbeam_channel::Sender<MuxInput>) -> ! {
+    let send = |input| updates.send(input).expect("Failed to send");
     loop {
-        if let Ok(ev) = device
-            .next_event(evdev_rs::ReadFlag::NORMAL)
-            .map(|val| val.1)
-        {
-            updates
-                .send(AxisUpdate {
-                    joystick: id,
-                    event: ev,
-                })
-                .expect("Failed to send");
+        for update in input.current_state() {
+            send(MuxInput::Seed(update));
         }
+        let err = loop {
+            let err = input.read_events(|update| send(MuxInput::Update(update)));
+            if reader::is_lost(&err) {
+                break err;
+            }
+            if let Err(err) = reader::wait_readable(&input.device) {
+                break err;
+            }
+        };
+        eprintln!("Lost {}: {err}", input.input.name);
+        updates
+            .send(MuxInput::Disconnected(input.id))
+            .expect("Failed to send");
+        input.reconnect_retrying();
+        eprintln!("Reconnected {}", input.input.name);
     }
 }
 
 static DEVICE_INDEX_SEQ: Mutex<u16> = Mutex::new(0);
-fn make_device<P: AsRef<std::path::Path>>(
-    path: P,
-) -> Result<(u16, evdev_rs::Device, HashMap<EventCode, InputAxis>)> {
+type OpenDevice = (
+    u16,
+    evdev_rs::Device,
+    HashMap<EventCode, InputAxis>,
+    Option<ConfigQuirk>,
+);
+
+/// Opens `input`'s device. `opened` has the device number of each one
+/// that's already open and the joystick it's for, since different paths
+/// or selectors can still turn out to be the same device.
+fn make_device(
+    input: &ConfigInput,
+    overrides: &[ConfigQuirk],
+    opened: &mut HashMap<u64, String>,
+) -> Result<OpenDevice> {
     let mut idx = DEVICE_INDEX_SEQ.lock().unwrap();
-    let dev = evdev_rs::Device::new_from_path(path).context("failed to open device")?;
-    let axes = get_input_axes(&dev, *idx);
-    let result = (*idx, dev, axes);
+    let mut dev = device_selector::open_input(input).context("failed to open device")?;
+    let number = dev
+        .file()
+        .metadata()
+        .context("failed to look up device")?
+        .rdev();
+    if let Some(other) = opened.insert(number, input.name.clone()) {
+        anyhow::bail!("it's the same device as {other:?}");
+    }
+    reader::grab(&mut dev, input)?;
+    reader::use_monotonic_clock(&mut dev)?;
+    let quirk = quirks::find(overrides, dev.vendor_id(), dev.product_id());
+    let axes = get_input_axes(&dev, *idx, quirk.as_ref());
+    let result = (*idx, dev, axes, quirk);
     *idx += 1;
     Ok(result)
 }
 
-fn run() -> Result<()> {
-    let (update_s, update_r) = crossbeam_channel::bounded::<joystick_mux::AxisUpdate>(5);
-    let (output_s, output_r) = crossbeam_channel::bounded::<joystick_mux::OutputState>(5);
+/// Runs the mux on its own thread, with a thread reading each device
+/// and feeding it through a channel.
+fn run_threads(
+    inputs: Vec<OpenInput>,
+    mut mux: JoystickMux,
+    outputs: crossbeam_channel::Receiver<OutputState>,
+    mut axes_r: crossbeam_channel::Receiver<HashMap<OutputAxisId, AxisCombineFn>>,
+    mut send: impl FnMut(OutputState) -> Result<()>,
+) -> Result<()> {
+    let (update_s, update_r) = crossbeam_channel::bounded::<MuxInput>(5);
+    for input in inputs {
+        let s = update_s.clone();
+        thread::spawn(move || {
+            handle_device(input, s);
+        });
+    }
+    // Inputs that time out need the mux to notice time passing even
+    // when nothing is moving, but waking up for ticks that can't change
+    // anything only adds jitter.
+    let ticks = |mux: &JoystickMux| {
+        if mux.needs_ticks() {
+            crossbeam_channel::tick(TICK_INTERVAL)
+        } else {
+            crossbeam_channel::never()
+        }
+    };
+    thread::spawn(move || {
+        let mut tick_r = ticks(&mux);
+        loop {
+            crossbeam_channel::select! {
+                recv(update_r) -> input => if let Ok(input) = input {
+                    mux.handle(input);
+                },
+                recv(tick_r) -> _ => mux.handle(MuxInput::Tick(reader::now())),
+                recv(axes_r) -> axes => match axes {
+                    Ok(axes) => {
+                        mux.swap_axes(axes);
+                        mux.send_output();
+                        tick_r = ticks(&mux);
+                    }
+                    // Nothing will reload the config any more, but the
+                    // current mapping is still good.
+                    Err(_) => axes_r = crossbeam_channel::never(),
+                },
+            }
+        }
+    });
 
-    let (js_idx, js_device, js_axes) =
-        make_device("/dev/input/by-id/usb-Thrustmaster_T.16000M-event-joystick")
-            .context("while opening joystick")?;
+    loop {
+        if let Ok(output) = outputs.recv() {
+            send(output)?;
+        }
+    }
+}
 
-    let (sp_idx, sp_device, sp_axes) =
-        make_device("/dev/input/by-id/usb-3Dconnexion_SpaceMouse_Pro-event-mouse")
-            .context("while opening spacemouse")?;
+fn run(
+    output_kind: OutputKind,
+    auto_range: bool,
+    event_loop: EventLoop,
+    latency: bool,
+) -> Result<()> {
+    let (output_s, output_r) = match event_loop {
+        EventLoop::Threads => crossbeam_channel::bounded::<OutputState>(5),
+        EventLoop::Epoll => crossbeam_channel::unbounded(),
+    };
 
-    let (th_idx, th_device, th_axes) =
-        make_device("/dev/input/by-id/usb-Thrustmaster_TWCS_Throttle-event-joystick")
-            .context("while opening throttle")?;
+    let config_path = config_loader::config_file_path().context("Failed to find config")?;
+    let config = config_loader::load_config_from(&config_path).context("Failed to load config")?;
+    if let Some(err) = configuration::check_config(&config).into_iter().next() {
+        return Err(err).context("Invalid config");
+    }
 
-    let mut mux = joystick_mux::JoystickMux::new(Some(output_s));
-    configuration::configure_mux(&mut mux, &js_axes, &th_axes, &sp_axes);
+    let output_devices = configuration::output_devices(&config.outputs);
+    let description = configuration::report_description(&config.report);
 
-    let js_s = update_s.clone();
-    thread::spawn(move || {
-        handle_device(js_device, JoystickId(js_idx), js_s);
-    });
+    let mut inputs = Vec::new();
+    let mut joysticks = configuration::JoystickAxes::new();
+    let mut opened = HashMap::new();
+    for input in config.inputs.iter() {
+        let (idx, device, axes, quirk) = make_device(input, &config.quirks, &mut opened)
+            .with_context(|| format!("while opening {}", input.name))?;
+        joysticks.insert(input.name.clone(), axes);
+        inputs.push(OpenInput::new(
+            JoystickId(idx),
+            device,
+            input.clone(),
+            quirk,
+        ));
+    }
+    let calibration_path =
+        calibration::calibration_file_path().context("Failed to find calibration")?;
+    let calibration = calibration::load_calibration_from(&calibration_path)
+        .context("Failed to load calibration")?;
+    calibration::apply(&calibration, &mut joysticks).context("Invalid calibration")?;
 
-    let sp_s = update_s.clone();
-    thread::spawn(move || {
-        handle_device(sp_device, JoystickId(sp_idx), sp_s);
-    });
+    let mut mux = JoystickMux::new(Some(output_s));
+    mux.set_auto_range(auto_range);
+    mux.set_report_rate(configuration::report_rate(&config.report));
+    configuration::configure_mux(&mut mux, &config.outputs, &config.layers, &joysticks)
+        .context("Failed to configure outputs")?;
 
-    let th_s = update_s;
-    thread::spawn(move || {
-        handle_device(th_device, JoystickId(th_idx), th_s);
-    });
+    let (axes_s, axes_r) = crossbeam_channel::bounded(1);
+    let triggers = reload::spawn_triggers(&config_path).context("Failed to watch config")?;
+    let fixed = reload::Fixed {
+        inputs: config.inputs,
+        report: config.report,
+        quirks: config.quirks,
+        layers: config.layers,
+        output_devices,
+    };
+    reload::spawn_reloader(config_path, fixed, joysticks, triggers, axes_s);
 
-    thread::spawn(move || loop {
-        if let Ok(update) = update_r.recv() {
-            mux.update(update);
+    let mut sinks = (0..output_devices)
+        .map(|index| -> Result<Box<dyn OutputSink>> {
+            Ok(match output_kind {
+                OutputKind::Gadget => Box::new(GadgetSink::open(index, &description)?),
+                OutputKind::Uinput => Box::new(UinputSink::create(index, &description)?),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut latency = latency.then(Latency::default);
+    let send = move |output: OutputState| -> Result<()> {
+        for (index, sink) in sinks.iter_mut().enumerate() {
+            let state = output
+                .axes
+                .iter()
+                .filter(|(OutputAxisId { device, .. }, _)| *device == index)
+                .map(|(OutputAxisId { axis, .. }, value)| (*axis, *value))
+                .collect::<Vec<_>>();
+            sink.send(&state)?;
         }
-    });
+        if let (Some(latency), Some(time)) = (latency.as_mut(), output.time) {
+            latency.record(time);
+        }
+        Ok(())
+    };
 
-    let mut device = gadget::get_gadget_device().context("Failed to open gadget device")?;
-    loop {
-        if let Ok(output) = output_r.recv() {
-            let report = report::make_report(
-                output
-                    .axes
-                    .into_iter()
-                    .map(|(OutputAxisId(axis_id), value)| (axis_id, value)),
-            );
-            device
-                .write(&report)
-                .context("Failed to write to gadget device")?;
+    match event_loop {
+        EventLoop::Epoll => event_loop::run(inputs, mux, output_r, axes_r, TICK_INTERVAL, send),
+        EventLoop::Threads => run_threads(inputs, mux, output_r, axes_r, send),
+    }
+}
+
+fn wait_for_enter() -> Result<()> {
+    std::io::stdin()
+        .read_line(&mut String::new())
+        .context("Failed to read stdin")?;
+    Ok(())
+}
+
+fn calibrate() -> Result<()> {
+    let config = config_loader::load_config_file().context("Failed to load config")?;
+    let path = calibration::calibration_file_path().context("Failed to find calibration")?;
+    let mut calibration =
+        calibration::load_calibration_from(&path).context("Failed to load calibration")?;
+
+    println!("Leave every control at rest, then press Enter.");
+    wait_for_enter()?;
+    let mut recorders = Vec::new();
+    for input in config.inputs.iter() {
+        // Open the devices only now, so that they know where their
+        // axes are resting.
+        let mut device = device_selector::open_input(input)
+            .with_context(|| format!("Failed to open {}", input.name))?;
+        // Sweeping the axes shouldn't also throw the pointer around.
+        reader::grab(&mut device, input)?;
+        let recorder = Arc::new(Mutex::new(calibration::Recorder::for_device(&device)));
+        recorders.push((input.name.clone(), recorder.clone()));
+        let name = input.name.clone();
+        thread::spawn(move || loop {
+            match device.next_event(evdev_rs::ReadFlag::NORMAL) {
+                Ok((_, ev)) => recorder
+                    .lock()
+                    .unwrap()
+                    .observe(ev.event_code, ev.value.into()),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    if let Err(err) = reader::wait_readable(&device) {
+                        eprintln!("Lost {name}: {err}");
+                        return;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Lost {name}: {err}");
+                    return;
+                }
+            }
+        });
+    }
+
+    println!("Move every axis all the way in each direction, then press Enter.");
+    wait_for_enter()?;
+    for (name, recorder) in recorders {
+        let axes = recorder.lock().unwrap().finish();
+        for (axis, AxisCalibration { min, center, max }) in axes.iter() {
+            println!("{name} {axis}: {min} .. {center} .. {max}");
+        }
+        // Axes that weren't touched this time keep their old
+        // calibration.
+        calibration.entry(name).or_default().extend(axes);
+    }
+    calibration::save_calibration_to(&path, &calibration).context("Failed to save calibration")?;
+    println!("Saved to {}", path.display());
+    Ok(())
+}
+
+fn init(devices: Option<usize>, path: Option<&PathBuf>) -> Result<()> {
+    let (description, needed) = match path {
+        Some(path) => {
+            let config = config_loader::load_config_from(path).context("Failed to load config")?;
+            if let Some(err) = configuration::check_config(&config).into_iter().next() {
+                return Err(err).context("Invalid config");
+            }
+            (
+                configuration::report_description(&config.report),
+                configuration::output_devices(&config.outputs),
+            )
         }
+        None => (ReportDescription::default(), 1),
+    };
+    gadget::init_gadget(devices.unwrap_or(needed), &description)
+}
+
+fn check(path: Option<&PathBuf>) -> Result<()> {
+    let config = match path {
+        Some(path) => config_loader::load_config_from(path),
+        None => config_loader::load_config_file(),
+    }
+    .context("Failed to load config")?;
+
+    let errors = configuration::check_config(&config);
+    for err in errors.iter() {
+        println!("error: {err}");
+    }
+    println!(
+        "{} inputs, {} outputs, {} errors",
+        config.inputs.len(),
+        config.outputs.len(),
+        errors.len()
+    );
+    if !errors.is_empty() {
+        anyhow::bail!("Config has {} errors", errors.len());
+    }
+    Ok(())
+}
+
+fn list_devices() -> Result<()> {
+    for (path, device) in device_selector::list_devices()? {
+        let identity = device_selector::DeviceIdentity::of(&device);
+        println!("{}", path.display());
+        println!("  vendor: {:#06x}", identity.vendor);
+        println!("  product: {:#06x}", identity.product);
+        println!("  name: {}", identity.name.unwrap_or_default());
+        println!("  uniq: {}", identity.uniq.unwrap_or_default());
+        println!("  phys: {}", identity.phys.unwrap_or_default());
     }
+    Ok(())
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
     match &args.command {
-        Command::Init => gadget::init_gadget(),
+        Command::Init { devices, config } => init(*devices, config.as_ref()),
         Command::Uninit => gadget::uninit_gadget(),
-        Command::Run => run(),
+        Command::Run {
+            output,
+            auto_range,
+            event_loop,
+            latency,
+        } => run(*output, *auto_range, *event_loop, *latency),
+        Command::Calibrate => calibrate(),
+        Command::Check { config } => check(config.as_ref()),
+        Command::ListDevices => list_devices(),
     }
 }
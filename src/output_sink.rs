@@ -0,0 +1,117 @@
+use crate::descriptor::{ReportDescription, ReportLayout};
+use crate::gadget;
+use crate::report;
+use anyhow::{Context, Result};
+use evdev_rs::enums::{EventCode, EV_SYN};
+use evdev_rs::{AbsInfo, DeviceWrapper, EnableCodeData, InputEvent, TimeVal};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+
+// Linux's BUS_VIRTUAL, from linux/input.h.
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// Somewhere that the state of one emulated joystick goes.
+pub trait OutputSink {
+    fn send(&mut self, state: &[(EventCode, i64)]) -> Result<()>;
+}
+
+/// Sends HID reports to the host through /dev/hidg{index}.
+pub struct GadgetSink {
+    device: fs::File,
+    layout: ReportLayout,
+}
+
+impl GadgetSink {
+    pub fn open(index: usize, description: &ReportDescription) -> Result<Self> {
+        gadget::check_function(index, description)?;
+        let device = gadget::get_gadget_device(index).with_context(|| {
+            format!("Failed to open gadget device {index}, was init run with --config?")
+        })?;
+        Ok(GadgetSink {
+            device,
+            layout: description.layout(),
+        })
+    }
+}
+
+impl OutputSink for GadgetSink {
+    fn send(&mut self, state: &[(EventCode, i64)]) -> Result<()> {
+        let report = report::make_report(&self.layout, state.iter().copied());
+        self.device
+            .write(&report)
+            .context("Failed to write to gadget device")?;
+        Ok(())
+    }
+}
+
+/// A virtual joystick on this machine, for when there's no USB Device
+/// Controller or the games are running locally anyway.
+pub struct UinputSink {
+    device: evdev_rs::UInputDevice,
+    layout: ReportLayout,
+    codes: HashSet<EventCode>,
+}
+
+impl UinputSink {
+    pub fn create(index: usize, description: &ReportDescription) -> Result<Self> {
+        let uninit = evdev_rs::UninitDevice::new().context("Failed to create evdev device")?;
+        uninit.set_name(&format!("Composite Joystick {index}"));
+        uninit.set_bustype(BUS_VIRTUAL);
+        uninit.set_vendor_id(0x1d6b);
+        uninit.set_product_id(0x0104);
+        let mut codes = HashSet::new();
+        for (code, range) in report::codes(description) {
+            let data = match code {
+                EventCode::EV_ABS(_) => Some(EnableCodeData::AbsInfo(AbsInfo {
+                    value: 0,
+                    minimum: *range.start() as i32,
+                    maximum: *range.end() as i32,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                })),
+                _ => None,
+            };
+            uninit
+                .enable_event_code(&code, data)
+                .with_context(|| format!("Failed to enable {code}"))?;
+            codes.insert(code);
+        }
+        let device = evdev_rs::UInputDevice::create_from_device(&uninit)
+            .context("Failed to create uinput device, is the uinput module loaded?")?;
+        if let Some(devnode) = device.devnode() {
+            eprintln!("Created {devnode}");
+        }
+        Ok(UinputSink {
+            device,
+            layout: description.layout(),
+            codes,
+        })
+    }
+}
+
+impl OutputSink for UinputSink {
+    fn send(&mut self, state: &[(EventCode, i64)]) -> Result<()> {
+        // The kernel timestamps events from uinput itself.
+        let time = TimeVal::new(0, 0);
+        for (code, value) in state.iter() {
+            if !self.codes.contains(code) {
+                continue;
+            }
+            if let Some(value) = report::event_value(&self.layout, *code, *value) {
+                self.device
+                    .write_event(&InputEvent::new(&time, code, value as i32))
+                    .context("Failed to write to uinput device")?;
+            }
+        }
+        self.device
+            .write_event(&InputEvent::new(
+                &time,
+                &EventCode::EV_SYN(EV_SYN::SYN_REPORT),
+                0,
+            ))
+            .context("Failed to write to uinput device")?;
+        Ok(())
+    }
+}
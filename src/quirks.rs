@@ -0,0 +1,148 @@
+//! Fixes for devices that describe themselves wrong, from the table in
+//! quirks.yaml and the `quirks` section of the config.
+
+use crate::config_loader::ConfigQuirk;
+use crate::joystick_mux::{InputAxis, InputAxisId};
+use evdev_rs::enums::EventCode;
+use std::collections::HashMap;
+
+const BUILTIN: &str = include_str!("quirks.yaml");
+
+fn builtin() -> Vec<ConfigQuirk> {
+    serde_yaml::from_str(BUILTIN).expect("quirks.yaml should parse")
+}
+
+/// The quirk for a device, preferring the ones in `overrides`.
+pub fn find(overrides: &[ConfigQuirk], vendor: u16, product: u16) -> Option<ConfigQuirk> {
+    let matches = |quirk: &ConfigQuirk| quirk.vendor == vendor && quirk.product == product;
+    overrides
+        .iter()
+        .find(|quirk| matches(quirk))
+        .cloned()
+        .or_else(|| builtin().into_iter().find(matches))
+}
+
+/// The code that events with `code` from a device with `quirk` should
+/// be treated as.
+pub fn remap(quirk: Option<&ConfigQuirk>, code: EventCode) -> EventCode {
+    quirk
+        .and_then(|quirk| quirk.buttons.iter().find(|button| button.from == code))
+        .map_or(code, |button| button.to)
+}
+
+/// Fixes up the axes that `get_input_axes` found on a device with
+/// `quirk`.
+pub fn apply(quirk: &ConfigQuirk, axes: &mut HashMap<EventCode, InputAxis>) {
+    for button in quirk.buttons.iter() {
+        if let Some(axis) = axes.remove(&button.from) {
+            let id = InputAxisId {
+                axis: button.to,
+                ..axis.id
+            };
+            axes.insert(button.to, InputAxis { id, ..axis });
+        }
+    }
+    for fix in quirk.axes.iter() {
+        if let Some(axis) = axes.get_mut(&fix.axis) {
+            axis.lower_bound = fix.min.unwrap_or(axis.lower_bound);
+            axis.upper_bound = fix.max.unwrap_or(axis.upper_bound);
+            if let Some(offset) = fix.center {
+                axis.center = Some((axis.lower_bound + axis.upper_bound) / 2 + offset);
+            }
+            if fix.invert {
+                *axis = -axis.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_loader::{ConfigQuirkAxis, ConfigQuirkButton};
+    use crate::joystick_mux::JoystickId;
+    use evdev_rs::enums::{EV_KEY, EV_REL};
+
+    fn axis(code: EventCode, lower_bound: i64, upper_bound: i64) -> InputAxis {
+        InputAxis::new(
+            InputAxisId {
+                joystick: JoystickId(0),
+                axis: code,
+            },
+            lower_bound,
+            upper_bound,
+        )
+    }
+
+    #[test]
+    fn test_builtin_parses() {
+        let spacemouse = find(&[], 0x046d, 0xc62b).expect("SpaceMouse Pro should have a quirk");
+        assert_eq!(spacemouse.axes.len(), 6);
+        assert_eq!(spacemouse.axes[5].max, Some(350));
+        assert_eq!(find(&[], 0x044f, 0xb10a), None);
+    }
+
+    #[test]
+    fn test_overrides_win() {
+        let quirk = ConfigQuirk {
+            name: None,
+            vendor: 0x046d,
+            product: 0xc62b,
+            axes: vec![],
+            buttons: vec![],
+        };
+        let overrides = [quirk];
+        assert_eq!(find(&overrides, 0x046d, 0xc62b).as_ref(), overrides.first());
+    }
+
+    #[test]
+    fn test_apply() {
+        let x = EventCode::EV_REL(EV_REL::REL_X);
+        let y = EventCode::EV_REL(EV_REL::REL_Y);
+        let odd = EventCode::EV_KEY(EV_KEY::BTN_268);
+        let fixed = EventCode::EV_KEY(EV_KEY::BTN_0);
+        let quirk = ConfigQuirk {
+            name: None,
+            vendor: 1,
+            product: 2,
+            axes: vec![
+                ConfigQuirkAxis {
+                    axis: x,
+                    min: Some(-500),
+                    max: Some(500),
+                    invert: false,
+                    center: Some(10),
+                },
+                ConfigQuirkAxis {
+                    axis: y,
+                    min: None,
+                    max: None,
+                    invert: true,
+                    center: None,
+                },
+            ],
+            buttons: vec![ConfigQuirkButton {
+                from: odd,
+                to: fixed,
+            }],
+        };
+        let mut axes = HashMap::from([
+            (x, axis(x, -350, 350)),
+            (y, axis(y, -350, 350)),
+            (odd, axis(odd, 0, 1)),
+        ]);
+        apply(&quirk, &mut axes);
+        assert_eq!(
+            axes[&x],
+            InputAxis {
+                center: Some(10),
+                ..axis(x, -500, 500)
+            }
+        );
+        assert_eq!(axes[&y], axis(y, 350, -350));
+        assert_eq!(axes.get(&odd), None);
+        assert_eq!(axes[&fixed], axis(fixed, 0, 1));
+        assert_eq!(remap(Some(&quirk), odd), fixed);
+        assert_eq!(remap(Some(&quirk), x), x);
+    }
+}
@@ -0,0 +1,206 @@
+//! Reading events from the input devices, for either event loop.
+
+use crate::config_loader::{ConfigInput, ConfigQuirk};
+use crate::hotplug;
+use crate::joystick_mux::{AxisUpdate, JoystickId};
+use crate::quirks;
+use anyhow::{Context, Result};
+use evdev_rs::enums::{EventCode, EventType, EV_SYN};
+use evdev_rs::{DeviceWrapper, InputEvent, ReadFlag, ReadStatus, TimeVal};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+// How long to wait before trying again when waiting for a lost device
+// fails, doubling each time up to the max.
+const RECONNECT_RETRY_MIN: Duration = Duration::from_millis(100);
+const RECONNECT_RETRY_MAX: Duration = Duration::from_secs(10);
+
+/// Grabs `device` if `input` asks for it. The kernel lets go of the
+/// grab when the device is closed, so it's released however Composite
+/// Joystick exits.
+pub fn grab(device: &mut evdev_rs::Device, input: &ConfigInput) -> Result<()> {
+    if input.grab {
+        device.grab(evdev_rs::GrabMode::Grab).with_context(|| {
+            format!(
+                "Failed to grab {}, is something else grabbing it?",
+                input.name
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Has `device` stamp its events with the clock [`now`] reads rather
+/// than the wall clock, so that the mux times presses and ticks alike.
+pub fn use_monotonic_clock(device: &mut evdev_rs::Device) -> Result<()> {
+    device
+        .set_clock_id(libc::CLOCK_MONOTONIC)
+        .context("Failed to switch the device to the monotonic clock")
+}
+
+/// The time on the clock that input events are stamped with.
+pub fn now() -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// An event's timestamp as a time on the same clock as [`now`].
+pub fn timestamp(time: &TimeVal) -> Duration {
+    Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+}
+
+/// Blocks until `device` has events to read or has gone away. Meant for
+/// once reading it has run into `WouldBlock`, when libevdev has nothing
+/// queued either.
+pub fn wait_readable(device: &evdev_rs::Device) -> io::Result<()> {
+    let mut fd = libc::pollfd {
+        fd: device.file().as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    loop {
+        if unsafe { libc::poll(&mut fd, 1, -1) } >= 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Whether `err` from [`OpenInput::read_events`] means the device is
+/// gone, rather than just that it has nothing to say right now.
+pub fn is_lost(err: &io::Error) -> bool {
+    !matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
+
+/// An input device that's open and feeding the mux.
+pub struct OpenInput {
+    pub id: JoystickId,
+    pub device: evdev_rs::Device,
+    pub input: ConfigInput,
+    pub quirk: Option<ConfigQuirk>,
+    /// Whether libevdev is replaying the device's state after the
+    /// kernel dropped events because we fell behind.
+    syncing: bool,
+}
+
+impl OpenInput {
+    pub fn new(
+        id: JoystickId,
+        device: evdev_rs::Device,
+        input: ConfigInput,
+        quirk: Option<ConfigQuirk>,
+    ) -> Self {
+        OpenInput {
+            id,
+            device,
+            input,
+            quirk,
+            syncing: false,
+        }
+    }
+
+    fn update(&self, mut event: InputEvent) -> AxisUpdate {
+        event.event_code = quirks::remap(self.quirk.as_ref(), event.event_code);
+        AxisUpdate {
+            joystick: self.id,
+            event,
+        }
+    }
+
+    /// Where every axis and button on the device is right now, so that
+    /// the mux doesn't assume they're all at zero until they move. They
+    /// go to the mux as [`crate::joystick_mux::MuxInput::Seed`]s. Released
+    /// buttons are left out since the mux starts them that way.
+    pub fn current_state(&self) -> Vec<AxisUpdate> {
+        // Nothing stamped these, so they get no time of their own.
+        let time = TimeVal::new(0, 0);
+        let abs = evdev_rs::EventCodeIterator::new(&EventType::EV_ABS);
+        let key = evdev_rs::EventCodeIterator::new(&EventType::EV_KEY);
+        let mut result = Vec::new();
+        for code in abs.chain(key) {
+            match (code, self.device.event_value(&code)) {
+                (EventCode::EV_KEY(_), Some(0)) | (_, None) => {}
+                (code, Some(value)) => {
+                    result.push(self.update(InputEvent::new(&time, &code, value)))
+                }
+            }
+        }
+        result.push(self.update(InputEvent::new(
+            &time,
+            &EventCode::EV_SYN(EV_SYN::SYN_REPORT),
+            0,
+        )));
+        result
+    }
+
+    /// Reads events until the device runs out or fails, passing each
+    /// one to `handle`. Returns the error it stopped on, which is
+    /// `WouldBlock` when a non-blocking device has nothing more for
+    /// now.
+    pub fn read_events(&mut self, mut handle: impl FnMut(AxisUpdate)) -> io::Error {
+        // After a SYN_DROPPED, libevdev hands back the events that bring
+        // us up to date in sync mode.
+        loop {
+            let flag = if self.syncing {
+                ReadFlag::SYNC
+            } else {
+                ReadFlag::NORMAL
+            };
+            match self.device.next_event(flag) {
+                Ok((ReadStatus::Sync, ev)) => {
+                    self.syncing = true;
+                    // The events that follow bring the mux up to date,
+                    // and it doesn't send anything until the SYN_REPORT
+                    // at the end of them. SYN_DROPPED itself isn't
+                    // worth passing on.
+                    if !ev.is_code(&EventCode::EV_SYN(EV_SYN::SYN_DROPPED)) {
+                        handle(self.update(ev));
+                    }
+                }
+                Ok((ReadStatus::Success, ev)) => handle(self.update(ev)),
+                Err(err) if self.syncing && err.kind() == io::ErrorKind::WouldBlock => {
+                    self.syncing = false;
+                }
+                Err(err) => return err,
+            }
+        }
+    }
+
+    /// Blocks until the device can be opened again after it's been
+    /// lost.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.device = hotplug::wait_for_input(&self.input)?;
+        self.syncing = false;
+        use_monotonic_clock(&mut self.device)?;
+        if let Err(err) = grab(&mut self.device, &self.input) {
+            eprintln!("{err:?}");
+        }
+        Ok(())
+    }
+
+    /// [`Self::reconnect`], trying again with a backoff whenever waiting
+    /// for the device fails, so that a device is never given up on.
+    pub fn reconnect_retrying(&mut self) {
+        let mut delay = RECONNECT_RETRY_MIN;
+        while let Err(err) = self.reconnect() {
+            eprintln!(
+                "Failed to wait for {}, retrying in {delay:?}: {err:?}",
+                self.input.name
+            );
+            thread::sleep(delay);
+            delay = (delay * 2).min(RECONNECT_RETRY_MAX);
+        }
+    }
+}
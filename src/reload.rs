@@ -0,0 +1,141 @@
+use crate::config_loader::{self, ConfigInput, ConfigLayer, ConfigQuirk, ConfigReport};
+use crate::configuration::{self, JoystickAxes};
+use crate::joystick_mux::{AxisCombineFn, OutputAxisId};
+use anyhow::{Context, Result};
+use inotify::{Inotify, WatchMask};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Sends a message every time the process gets SIGHUP or the file at
+/// `path` is written.
+pub fn spawn_triggers(path: &Path) -> Result<crossbeam_channel::Receiver<()>> {
+    let (trigger_s, trigger_r) = crossbeam_channel::bounded::<()>(1);
+
+    let mut signals = Signals::new([SIGHUP]).context("Failed to register SIGHUP handler")?;
+    let signal_s = trigger_s.clone();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            // A reload that's already queued will pick up this
+            // change too, so there's no need to queue another.
+            let _ = signal_s.try_send(());
+        }
+    });
+
+    // Editors tend to replace the config file rather than writing to
+    // it, which would orphan a watch on the file itself, so watch the
+    // directory and filter by name.
+    let dir = path
+        .parent()
+        .context("Config file has no parent directory")?
+        .to_path_buf();
+    let name = path
+        .file_name()
+        .context("Config file has no file name")?
+        .to_os_string();
+    let mut inotify = Inotify::init().context("Failed to initialize inotify")?;
+    inotify
+        .watches()
+        .add(&dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    // SIGHUP still works, so keep running without the
+                    // watch rather than taking the process down.
+                    eprintln!(
+                        "Stopped watching {} for changes, send SIGHUP to reload: {err}",
+                        dir.display()
+                    );
+                    return;
+                }
+            };
+            if events
+                .into_iter()
+                .any(|event| event.name == Some(name.as_os_str()))
+            {
+                let _ = trigger_s.try_send(());
+            }
+        }
+    });
+
+    Ok(trigger_r)
+}
+
+/// The parts of the config that can't change without a restart, as
+/// they were when Composite Joystick started.
+pub struct Fixed {
+    pub inputs: Vec<ConfigInput>,
+    pub report: ConfigReport,
+    pub quirks: Vec<ConfigQuirk>,
+    pub layers: Vec<ConfigLayer>,
+    pub output_devices: usize,
+}
+
+/// Loads the config at `path` and builds a new set of outputs from it
+/// for the joysticks that are already open.
+pub fn load_axes(
+    path: &Path,
+    fixed: &Fixed,
+    joysticks: &JoystickAxes,
+) -> Result<HashMap<OutputAxisId, AxisCombineFn>> {
+    let config = config_loader::load_config_from(path).context("Failed to load config")?;
+    if let Some(err) = configuration::check_config(&config).into_iter().next() {
+        return Err(err).context("Invalid config");
+    }
+    if config.inputs != fixed.inputs {
+        anyhow::bail!("Inputs can't be changed while running, restart to apply");
+    }
+    if config.quirks != fixed.quirks {
+        anyhow::bail!("Quirks can't be changed while running, restart to apply");
+    }
+    if config.layers != fixed.layers {
+        anyhow::bail!("Layers can't be changed while running, restart to apply");
+    }
+    let shape = |report: &ConfigReport| ConfigReport {
+        min_rate: None,
+        max_rate: None,
+        ..report.clone()
+    };
+    if shape(&config.report) != shape(&fixed.report) {
+        anyhow::bail!("The report can't be changed while running, rerun init and restart to apply");
+    }
+    if config.report != fixed.report {
+        anyhow::bail!("Report rates can't be changed while running, restart to apply");
+    }
+    if configuration::output_devices(&config.outputs) > fixed.output_devices {
+        anyhow::bail!("Emulated joysticks can't be added while running, restart to apply");
+    }
+    configuration::build_axes(&config.outputs, &fixed.layers, joysticks)
+        .context("Failed to configure outputs")
+}
+
+/// Reloads the config at `path` every time `triggers` fires, sending
+/// the new outputs to `axes_s`. Configs that don't load are reported
+/// and ignored, so the mux keeps running with the last good mapping.
+pub fn spawn_reloader(
+    path: PathBuf,
+    fixed: Fixed,
+    joysticks: JoystickAxes,
+    triggers: crossbeam_channel::Receiver<()>,
+    axes_s: crossbeam_channel::Sender<HashMap<OutputAxisId, AxisCombineFn>>,
+) {
+    thread::spawn(move || {
+        for () in triggers {
+            match load_axes(&path, &fixed, &joysticks) {
+                Ok(axes) => {
+                    eprintln!("Reloaded {}", path.display());
+                    axes_s.send(axes).expect("Failed to send new outputs");
+                }
+                Err(err) => eprintln!("Keeping old config: {err:?}"),
+            }
+        }
+    });
+}
@@ -1,123 +1,177 @@
-use evdev_rs::enums::{EventCode, EV_ABS, EV_KEY};
-use packed_struct::prelude::*;
+use crate::descriptor::{AxisUsage, Field, ReportDescription, ReportLayout};
+use crate::joystick_mux::OUTPUT_UPPER_BOUND;
+use evdev_rs::enums::{EventCode, EventType, EV_ABS};
+use std::ops::RangeInclusive;
 
-#[derive(PackedStruct, Debug)]
-#[packed_struct(bit_numbering = "msb0")]
-pub struct CompositeJoystickReport {
-    #[packed_field(bits = "0..16", endian = "lsb")]
-    pub x: i16,
-    #[packed_field(bits = "16..32", endian = "lsb")]
-    pub y: i16,
-    #[packed_field(bits = "32..48", endian = "lsb")]
-    pub z: i16,
-    #[packed_field(bits = "48..64", endian = "lsb")]
-    pub rx: i16,
-    #[packed_field(bits = "64..80", endian = "lsb")]
-    pub ry: i16,
-    #[packed_field(bits = "80..96", endian = "lsb")]
-    pub rz: i16,
-    #[packed_field(bits = "96..112", endian = "lsb")]
-    pub slider: i16,
-    #[packed_field(bits = "112..128", endian = "lsb")]
-    pub dial: i16,
-    #[packed_field(bits = "128..132", endian = "lsb")]
-    pub hat: Integer<u8, packed_bits::Bits<4>>,
-    #[packed_field(bits = "132..176", element_size_bits = "1")]
-    pub buttons: [bool; 44],
+// The old hand-written descriptor put the Dial usage on the throttle
+// and Slider on the rudder. Keep it that way so that bindings in games
+// don't move around.
+const AXES: [(EV_ABS, AxisUsage); 9] = [
+    (EV_ABS::ABS_X, AxisUsage::X),
+    (EV_ABS::ABS_Y, AxisUsage::Y),
+    (EV_ABS::ABS_Z, AxisUsage::Z),
+    (EV_ABS::ABS_RX, AxisUsage::Rx),
+    (EV_ABS::ABS_RY, AxisUsage::Ry),
+    (EV_ABS::ABS_RZ, AxisUsage::Rz),
+    (EV_ABS::ABS_THROTTLE, AxisUsage::Dial),
+    (EV_ABS::ABS_RUDDER, AxisUsage::Slider),
+    (EV_ABS::ABS_WHEEL, AxisUsage::Wheel),
+];
+
+const HATS: [(EV_ABS, EV_ABS); 4] = [
+    (EV_ABS::ABS_HAT0X, EV_ABS::ABS_HAT0Y),
+    (EV_ABS::ABS_HAT1X, EV_ABS::ABS_HAT1Y),
+    (EV_ABS::ABS_HAT2X, EV_ABS::ABS_HAT2Y),
+    (EV_ABS::ABS_HAT3X, EV_ABS::ABS_HAT3Y),
+];
+
+// Buttons are numbered the same way Linux numbers the buttons of a HID
+// joystick, so a Linux host sees the same event code that drives each
+// button: BTN_TRIGGER through the unnamed code before BTN_A are
+// buttons 1 through 16, and BTN_TRIGGER_HAPPY1 through KEY_MAX are 17
+// through 80. Linux ignores anything past that, so the gamepad and
+// mouse buttons fill out the rest for other hosts.
+const BUTTON_CODES: [RangeInclusive<u32>; 4] =
+    [0x120..=0x12f, 0x2c0..=0x2ff, 0x130..=0x13f, 0x100..=0x11f];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Slot {
+    Axis(AxisUsage),
+    HatX(usize),
+    HatY(usize),
+    Button(usize),
+}
+
+fn slot(code: EventCode) -> Option<Slot> {
+    match code {
+        EventCode::EV_ABS(abs) => AXES
+            .iter()
+            .find(|(axis, _)| *axis == abs)
+            .map(|(_, usage)| Slot::Axis(*usage))
+            .or_else(|| {
+                HATS.iter().enumerate().find_map(|(idx, (x, y))| {
+                    if *x == abs {
+                        Some(Slot::HatX(idx))
+                    } else if *y == abs {
+                        Some(Slot::HatY(idx))
+                    } else {
+                        None
+                    }
+                })
+            }),
+        EventCode::EV_KEY(_) => {
+            let (_, code) = evdev_rs::util::event_code_to_int(&code);
+            let mut first_button = 0;
+            for codes in BUTTON_CODES.iter() {
+                if codes.contains(&code) {
+                    return Some(Slot::Button(first_button + (code - codes.start()) as usize));
+                }
+                first_button += codes.clone().count();
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Whether `code` drives anything in a report shaped like
+/// `description`.
+pub fn has_slot(description: &ReportDescription, code: EventCode) -> bool {
+    match slot(code) {
+        Some(Slot::Axis(usage)) => description.axes.contains(&usage),
+        Some(Slot::HatX(idx) | Slot::HatY(idx)) => idx < description.hats,
+        Some(Slot::Button(idx)) => idx < description.buttons,
+        None => false,
+    }
+}
+
+/// Converts an axis from the mux's range to the report's.
+fn scale_axis(layout: &ReportLayout, value: i64) -> i64 {
+    (value * layout.axis_max / OUTPUT_UPPER_BOUND).clamp(layout.axis_min, layout.axis_max)
 }
 
-pub fn make_report(state: impl Iterator<Item = (EventCode, i64)>) -> [u8; 22] {
-    let mut result = CompositeJoystickReport {
-        x: 0,
-        y: 0,
-        z: 0,
-        rx: 0,
-        ry: 0,
-        rz: 0,
-        slider: 0,
-        dial: 0,
-        hat: (15).into(),
-        buttons: [false; 44],
-    };
-    let mut hatx: i64 = 0;
-    let mut haty: i64 = 0;
+/// Every event code that drives part of a report shaped like
+/// `description`, with the range of values that it takes in
+/// [`event_value`].
+pub fn codes(description: &ReportDescription) -> Vec<(EventCode, RangeInclusive<i64>)> {
+    let layout = description.layout();
+    let mut result = Vec::new();
+    for usage in description.axes.iter() {
+        if let Some((axis, _)) = AXES.iter().find(|(_, u)| u == usage) {
+            result.push((EventCode::EV_ABS(*axis), layout.axis_min..=layout.axis_max));
+        }
+    }
+    for (x, y) in HATS.iter().take(description.hats) {
+        result.push((EventCode::EV_ABS(*x), -1..=1));
+        result.push((EventCode::EV_ABS(*y), -1..=1));
+    }
+    let buttons = BUTTON_CODES.iter().flat_map(|codes| codes.clone());
+    for code in buttons.take(description.buttons) {
+        match evdev_rs::util::int_to_event_code(EventType::EV_KEY as u32, code) {
+            // Not every button has a name in evdev_rs.
+            EventCode::EV_UNK { .. } => {}
+            code => result.push((code, 0..=1)),
+        }
+    }
+    result
+}
+
+/// The value that `code` has in a report laid out like `layout`, or
+/// `None` if it isn't in the report.
+pub fn event_value(layout: &ReportLayout, code: EventCode, value: i64) -> Option<i64> {
+    match slot(code)? {
+        Slot::Axis(usage) if layout.axes.iter().any(|(u, _)| *u == usage) => {
+            Some(scale_axis(layout, value))
+        }
+        Slot::HatX(idx) | Slot::HatY(idx) if idx < layout.hats.len() => Some(value.signum()),
+        Slot::Button(idx) if idx < layout.buttons.len() => Some((value != 0).into()),
+        _ => None,
+    }
+}
+
+pub fn make_report(
+    layout: &ReportLayout,
+    state: impl Iterator<Item = (EventCode, i64)>,
+) -> Vec<u8> {
+    let mut report = vec![0; layout.length];
+    let mut hatx = vec![0; layout.hats.len()];
+    let mut haty = vec![0; layout.hats.len()];
     for (code, value) in state {
-        match code {
-            EventCode::EV_ABS(EV_ABS::ABS_X) => result.x = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_Y) => result.y = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_Z) => result.z = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_RX) => result.rx = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_RY) => result.ry = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_RZ) => result.rz = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_THROTTLE) => result.slider = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_RUDDER) => result.dial = value as i16,
-            EventCode::EV_ABS(EV_ABS::ABS_HAT0X) => hatx = value.signum(),
-            EventCode::EV_ABS(EV_ABS::ABS_HAT0Y) => haty = value.signum(),
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER) => result.buttons[0] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_THUMB) => result.buttons[1] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_THUMB2) => result.buttons[2] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TOP) => result.buttons[3] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TOP2) => result.buttons[4] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_PINKIE) => result.buttons[5] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_BASE) => result.buttons[6] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_BASE2) => result.buttons[7] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_BASE3) => result.buttons[8] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_BASE4) => result.buttons[9] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_BASE5) => result.buttons[10] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_BASE6) => result.buttons[11] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY1) => result.buttons[12] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY2) => result.buttons[13] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY3) => result.buttons[14] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY4) => result.buttons[15] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY5) => result.buttons[16] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY6) => result.buttons[17] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY7) => result.buttons[18] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY8) => result.buttons[19] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY9) => result.buttons[20] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY10) => result.buttons[21] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY11) => result.buttons[22] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY12) => result.buttons[23] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY13) => result.buttons[24] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY14) => result.buttons[25] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY15) => result.buttons[26] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY16) => result.buttons[27] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY17) => result.buttons[28] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY18) => result.buttons[29] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY19) => result.buttons[30] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY20) => result.buttons[31] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY21) => result.buttons[32] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY22) => result.buttons[33] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY23) => result.buttons[34] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY24) => result.buttons[35] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY25) => result.buttons[36] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY26) => result.buttons[37] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY27) => result.buttons[38] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY28) => result.buttons[39] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY29) => result.buttons[40] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY30) => result.buttons[41] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY31) => result.buttons[42] = value != 0,
-            EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY32) => result.buttons[43] = value != 0,
+        match slot(code) {
+            Some(Slot::Axis(usage)) => {
+                if let Some((_, field)) = layout.axes.iter().find(|(u, _)| *u == usage) {
+                    put_bits(&mut report, *field, scale_axis(layout, value) as u64);
+                }
+            }
+            Some(Slot::HatX(idx)) if idx < hatx.len() => hatx[idx] = value.signum(),
+            Some(Slot::HatY(idx)) if idx < haty.len() => haty[idx] = value.signum(),
+            Some(Slot::Button(idx)) if idx < layout.buttons.len() => {
+                put_bits(&mut report, layout.buttons[idx], (value != 0).into())
+            }
             _ => {}
         }
     }
-
-    // Have to flip the hat bits ourselves (0..3 -> 3..0) because
-    // packed_bits only handles *byte* ordering, not *bit* ordering
-    result.hat = flip_hat_bits(hatxy_to_angle(hatx, haty)).into();
-    let mut bytes = result.pack().expect("Failed to pack report to bytes");
-    // packed_struct can't handle endianness on bit arrays, so we have
-    // to do this ourselves
-    bytes[21] = bytes[21].reverse_bits();
-    bytes[20] = bytes[20].reverse_bits();
-    bytes[19] = bytes[19].reverse_bits();
-    bytes[18] = bytes[18].reverse_bits();
-    bytes[17] = bytes[17].reverse_bits();
-    bytes[16] = bytes[16].reverse_bits();
-    bytes
+    for (idx, field) in layout.hats.iter().enumerate() {
+        put_bits(
+            &mut report,
+            *field,
+            hatxy_to_angle(hatx[idx], haty[idx]).into(),
+        );
+    }
+    report
 }
 
-fn flip_hat_bits(hat: u8) -> u8 {
-    hat.reverse_bits() >> 4
+/// Writes the low `field.size` bits of `value` into `report`, least
+/// significant bit first like HID expects.
+fn put_bits(report: &mut [u8], field: Field, value: u64) {
+    for bit in 0..field.size {
+        let pos = field.offset + bit;
+        if value & (1 << bit) != 0 {
+            report[pos / 8] |= 1 << (pos % 8);
+        } else {
+            report[pos / 8] &= !(1 << (pos % 8));
+        }
+    }
 }
 
 fn hatxy_to_angle(hatx: i64, haty: i64) -> u8 {
@@ -143,14 +197,19 @@ fn hatxy_to_angle(hatx: i64, haty: i64) -> u8 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use evdev_rs::enums::EV_KEY;
+
+    fn layout() -> ReportLayout {
+        ReportDescription::default().layout()
+    }
 
     #[test]
     fn test_hat_zero() {
         assert_eq!(
-            make_report(vec! {}.into_iter()),
+            make_report(&layout(), vec! {}.into_iter()),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -159,6 +218,7 @@ mod tests {
     fn test_hat_plus_x() {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 1),
                 }
@@ -166,7 +226,7 @@ mod tests {
             ),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -175,6 +235,7 @@ mod tests {
     fn test_hat_plus_y() {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_ABS(EV_ABS::ABS_HAT0Y), 1),
                 }
@@ -182,7 +243,7 @@ mod tests {
             ),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -191,6 +252,7 @@ mod tests {
     fn test_hat_combines() {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_ABS(EV_ABS::ABS_HAT0Y), 1),
                     (EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 1),
@@ -199,7 +261,7 @@ mod tests {
             ),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -208,6 +270,7 @@ mod tests {
     fn test_button_firstbyte() {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_KEY(EV_KEY::BTN_TRIGGER), 1),
                 }
@@ -215,7 +278,7 @@ mod tests {
             ),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -224,6 +287,7 @@ mod tests {
     fn test_button_nextbyte() {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_KEY(EV_KEY::BTN_TOP2), 1),
                 }
@@ -231,7 +295,7 @@ mod tests {
             ),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x0f, 0x01, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x0f, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -240,6 +304,7 @@ mod tests {
     fn test_button_combo_bytes() {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_KEY(EV_KEY::BTN_TOP2), 1),
                     (EventCode::EV_KEY(EV_KEY::BTN_PINKIE), 1),
@@ -248,7 +313,7 @@ mod tests {
             ),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x0f, 0x03, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x0f, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -257,6 +322,7 @@ mod tests {
     fn test_button_lastbyte() {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY32), 1),
                 }
@@ -264,14 +330,62 @@ mod tests {
             ),
             [
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x80
+                0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08
             ]
         );
     }
 
+    #[test]
+    fn test_button_numbers_match_linux() {
+        let button = |code| match slot(code) {
+            Some(Slot::Button(idx)) => idx + 1,
+            other => panic!("{other:?} is not a button"),
+        };
+        assert_eq!(button(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER)), 1);
+        assert_eq!(button(EventCode::EV_KEY(EV_KEY::BTN_BASE6)), 12);
+        assert_eq!(button(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY1)), 17);
+        assert_eq!(slot(EventCode::EV_KEY(EV_KEY::KEY_A)), None);
+    }
+
+    #[test]
+    fn test_small_report() {
+        let layout = ReportDescription {
+            axes: vec![AxisUsage::X, AxisUsage::Wheel],
+            hats: 2,
+            buttons: 4,
+            axis_bits: 8,
+        }
+        .layout();
+        assert_eq!(
+            make_report(
+                &layout,
+                vec! {
+                    (EventCode::EV_ABS(EV_ABS::ABS_X), -32767),
+                    (EventCode::EV_ABS(EV_ABS::ABS_WHEEL), 16384),
+                    (EventCode::EV_ABS(EV_ABS::ABS_HAT1Y), -1),
+                    (EventCode::EV_KEY(EV_KEY::BTN_THUMB2), 1),
+                    (EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY1), 1),
+                }
+                .into_iter()
+            ),
+            [0x81, 0x3f, 0x0f, 0x04]
+        );
+    }
+
+    #[test]
+    fn test_codes() {
+        let codes = codes(&ReportDescription::default());
+        assert_eq!(codes.len(), 8 + 2 + 48);
+        assert!(codes.contains(&(EventCode::EV_ABS(EV_ABS::ABS_THROTTLE), -32767..=32767)));
+        assert!(codes.contains(&(EventCode::EV_ABS(EV_ABS::ABS_HAT0Y), -1..=1)));
+        assert!(codes.contains(&(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY32), 0..=1)));
+        assert!(!codes.contains(&(EventCode::EV_KEY(EV_KEY::BTN_TRIGGER_HAPPY33), 0..=1)));
+    }
+
     fn assert_x(x: i64, bytea: u8, byteb: u8) {
         assert_eq!(
             make_report(
+                &layout(),
                 vec! {
                     (EventCode::EV_ABS(EV_ABS::ABS_X), x),
                 }
@@ -279,7 +393,7 @@ mod tests {
             ),
             [
                 bytea, byteb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }